@@ -0,0 +1,64 @@
+use crate::bridge::Bridge;
+use std::{collections::HashSet, error::Error, time::Duration};
+use tf_libp2p::{Handler, PeerId};
+use tf_stellar::fetch_peer_id_from_account;
+use tokio::{sync::watch, time};
+use tracing::{debug, info, warn};
+
+// Signers are rarely added or removed, a few minutes of delay is fine
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Peer ids the signers of the bridge account registered in their "id" data entry.
+pub async fn signer_peer_ids(bridge: &Bridge) -> Result<HashSet<PeerId>, Box<dyn Error>> {
+    let signers = bridge.node_signers().await?;
+
+    let mut peers = HashSet::new();
+    for signer in signers {
+        match fetch_peer_id_from_account(signer.key.clone(), bridge.network.clone()).await? {
+            Some(id) => match id.parse() {
+                Ok(peer) => {
+                    peers.insert(peer);
+                }
+                Err(e) => warn!(
+                    "signer {} registered an invalid peer id {}: {}",
+                    signer.key, id, e
+                ),
+            },
+            None => debug!("signer {} has no peer id registered", signer.key),
+        }
+    }
+    Ok(peers)
+}
+
+/// Only let the nodes of the signers of the bridge account connect, refreshing
+/// the list every few minutes until `shutdown` is set. A failed refresh keeps
+/// the previous list.
+pub async fn run(handler: Handler, bridge: Bridge, mut shutdown: watch::Receiver<bool>) {
+    let mut interval = time::interval(REFRESH_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            Ok(()) = shutdown.changed() => break,
+        }
+
+        let peers = match signer_peer_ids(&bridge).await {
+            Ok(peers) => peers,
+            Err(e) => {
+                warn!("failed to refresh the peer allowlist: {}", e);
+                continue;
+            }
+        };
+        // Most likely a misconfigured bridge account, don't cut the node off
+        if peers.is_empty() {
+            warn!(
+                "no signer of {} registered a peer id, keeping the allowlist",
+                bridge.account
+            );
+            continue;
+        }
+        info!("allowing the {} peers of the bridge signers", peers.len());
+        if handler.allow_peers(peers).is_err() {
+            break;
+        }
+    }
+}
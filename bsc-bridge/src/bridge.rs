@@ -0,0 +1,45 @@
+use std::error::Error;
+use tf_stellar::{fetch_singers_from_account, network::StellarNetwork, Asset, PublicKey, Signer};
+
+// Horizon's type of the signers which are plain Stellar keys
+const ED25519_SIGNER: &str = "ed25519_public_key";
+
+/// Settings of the bridge shared by the orchestrator and the signer.
+#[derive(Clone)]
+pub struct Bridge {
+    /// Stellar account holding the deposited tokens, controlled by the signers
+    pub account: String,
+    pub network: StellarNetwork,
+    pub asset_code: String,
+    pub asset_issuer: String,
+    /// Signatures needed for a mint or a payout
+    pub min_signatures: usize,
+    /// Only sign for the nodes which signed their request with a key of the bridge account
+    pub require_signed_requests: bool,
+}
+
+impl Bridge {
+    /// The Stellar asset which is bridged
+    pub fn asset(&self) -> Result<Asset, Box<dyn Error>> {
+        let issuer = PublicKey::from_account_id(&self.asset_issuer)?;
+        Ok(Asset::new_credit(self.asset_code.as_str(), issuer)?)
+    }
+
+    /// Keys which can sign for the bridge account, the master key included when it has a weight.
+    pub async fn signers(&self) -> Result<Vec<Signer>, Box<dyn Error>> {
+        let signers =
+            fetch_singers_from_account(self.account.clone(), self.network.clone()).await?;
+        Ok(signers
+            .into_iter()
+            .filter(|s| s.type_ == ED25519_SIGNER && s.weight > 0)
+            .collect())
+    }
+
+    /// Signers of the bridge account which run a node.
+    pub async fn node_signers(&self) -> Result<Vec<Signer>, Box<dyn Error>> {
+        let mut signers = self.signers().await?;
+        // The master key of the bridge account itself is not a node
+        signers.retain(|s| s.key != self.account);
+        Ok(signers)
+    }
+}
@@ -0,0 +1,352 @@
+use crate::{
+    config::{Config, CONFIG_ENV, DEFAULT_CONFIG_FILE, LOG_FORMAT_ENV},
+    health,
+    keystore::{self, Keys},
+    remote_key::RemoteSigner,
+    store::Store,
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::{error::Error, future::Future, path::PathBuf, sync::Arc, time::Duration};
+use tf_bsc::keys::{KeyBackend as BscKeyBackend, LocalKey as BscLocalKey};
+use tf_libp2p::{
+    generate_psk, get_psk,
+    relay::{RelayLimits, RelayServer},
+    Keypair, PeerId,
+};
+use tf_stellar::{
+    fetch_peer_id_from_account, generate_secret,
+    keys::{KeyBackend as StellarKeyBackend, LocalKey as StellarLocalKey},
+    Client,
+};
+
+#[derive(Parser)]
+#[command(version, about = "Bridge of TFT between Stellar and BSC")]
+pub struct Cli {
+    /// Path of the config file
+    #[arg(short, long, global = true, env = CONFIG_ENV, default_value = DEFAULT_CONFIG_FILE)]
+    pub config: PathBuf,
+    /// Format of the logs, json to collect them from every signer and follow a transfer across nodes
+    #[arg(long, global = true, env = LOG_FORMAT_ENV, default_value = "text")]
+    pub log_format: LogFormat,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Start the bridge node
+    Run,
+    /// Create a keystore with new libp2p and BSC keys, and print the peer id
+    Keygen {
+        /// Path of the keystore to create, the one of the config file by default
+        #[arg(long)]
+        keystore: Option<PathBuf>,
+        /// File holding the password of the keystore, it is prompted for when not set
+        #[arg(long)]
+        password_file: Option<PathBuf>,
+        /// Prompt for the secret seed of an existing Stellar signer instead of generating one
+        #[arg(long)]
+        import_stellar: bool,
+        /// Only create the libp2p identity, the Stellar and BSC keys are held by a remote signer
+        #[arg(long, conflicts_with = "import_stellar")]
+        identity_only: bool,
+    },
+    /// Generate the swarm.key of a new private network
+    Psk {
+        /// Directory to write the swarm.key to
+        #[arg(long, default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Publish the peer id of this node in the "id" data entry of its Stellar signer account
+    RegisterPeer,
+    /// Show the signers of the bridge account and the peer ids they registered
+    Signers,
+    /// Show the progress of the node, and its network when the node runs with [health]
+    Status,
+    /// Run a relay of the private network, for signers which can't be reached directly
+    Relay(RelayArgs),
+}
+
+#[derive(Args)]
+pub struct RelayArgs {
+    /// Keystore holding the identity of the relay, created with `keygen --identity-only`
+    #[arg(long, default_value = "data/keystore.json")]
+    keystore: PathBuf,
+    /// File holding the password of the keystore, it is prompted for when not set
+    #[arg(long)]
+    password_file: Option<PathBuf>,
+    /// Directory holding the swarm.key of the private network
+    #[arg(long, default_value = ".")]
+    psk_dir: PathBuf,
+    /// Addresses to listen on
+    #[arg(long, default_value = "/ip4/0.0.0.0/tcp/4001")]
+    listen: Vec<String>,
+    /// Public addresses the signers reach the relay on, the listen addresses when not set
+    #[arg(long)]
+    external: Vec<String>,
+    /// Peers which can hold a reservation at the same time
+    #[arg(long)]
+    max_reservations: Option<usize>,
+    /// Circuits which can be open at the same time
+    #[arg(long)]
+    max_circuits: Option<usize>,
+    /// Seconds after which a circuit is closed
+    #[arg(long)]
+    max_circuit_duration: Option<u64>,
+    /// Bytes after which a circuit is closed
+    #[arg(long)]
+    max_circuit_bytes: Option<u64>,
+}
+
+pub fn keygen(
+    keystore: PathBuf,
+    password_file: Option<PathBuf>,
+    import_stellar: bool,
+    identity_only: bool,
+) -> Result<(), Box<dyn Error>> {
+    if keystore.exists() {
+        return Err(format!("{} already exists", keystore.display()).into());
+    }
+
+    let mut keys = Keys {
+        stellar_secret: None,
+        bsc_secret: None,
+        identity: Keypair::generate_ed25519(),
+    };
+    let mut bsc_address = None;
+    if !identity_only {
+        keys.stellar_secret = Some(if import_stellar {
+            rpassword::prompt_password("stellar secret seed: ")?
+        } else {
+            generate_secret()?
+        });
+        let (secret, address) = tf_bsc::generate_key();
+        keys.bsc_secret = Some(secret);
+        bsc_address = Some(address);
+    }
+
+    let password = match password_file {
+        Some(file) => keystore::read_password(Some(&file), "")?,
+        None => {
+            let password = rpassword::prompt_password("keystore password: ")?;
+            if rpassword::prompt_password("repeat password: ")? != password {
+                return Err("passwords don't match".into());
+            }
+            password
+        }
+    };
+    keystore::create(&keystore, &keys, &password)?;
+
+    println!("peer id:         {}", PeerId::from(keys.identity.public()));
+    if let Some(secret) = &keys.stellar_secret {
+        let stellar = StellarLocalKey::from_secret_seed(secret)?;
+        println!("stellar address: {}", stellar.public_key().account_id());
+    }
+    if let Some(address) = bsc_address {
+        println!("bsc address:     {:?}", address);
+    }
+    println!();
+    println!("the keys were written to {}", keystore.display());
+    Ok(())
+}
+
+/// Unlock the keystore of the config, with the password file or a prompt.
+pub fn unlock_keys(config: &Config) -> Result<Keys, Box<dyn Error>> {
+    let password = keystore::read_password(
+        config.keystore.password_file.as_deref(),
+        "keystore password: ",
+    )?;
+    keystore::unlock(&config.keystore.path, &password)
+        .map_err(|e| format!("failed to unlock {}: {}", config.keystore.path.display(), e).into())
+}
+
+/// The Stellar and BSC keys of this signer, from the remote signer when one is
+/// configured and from the unlocked keystore otherwise.
+pub async fn signing_keys(
+    config: &Config,
+    keys: &Keys,
+) -> Result<(Arc<dyn StellarKeyBackend>, Arc<dyn BscKeyBackend>), Box<dyn Error>> {
+    if let Some(remote) = &config.remote_signer {
+        let signer = RemoteSigner::new(&remote.url)?;
+        let stellar = signer.stellar_key(&remote.stellar_key).await?;
+        let bsc = signer.bsc_key(&remote.bsc_key).await?;
+        return Ok((Arc::new(stellar), Arc::new(bsc)));
+    }
+
+    match (&keys.stellar_secret, &keys.bsc_secret) {
+        (Some(stellar), Some(bsc)) => Ok((
+            Arc::new(StellarLocalKey::from_secret_seed(stellar)?),
+            Arc::new(BscLocalKey::from_secret(bsc)?),
+        )),
+        _ => Err(format!(
+            "{} only holds the libp2p identity, configure a [remote_signer] for the other keys",
+            config.keystore.path.display()
+        )
+        .into()),
+    }
+}
+
+pub fn psk(dir: PathBuf) -> Result<(), Box<dyn Error>> {
+    if get_psk(&dir)?.is_some() {
+        return Err(format!("{} already holds a swarm.key", dir.display()).into());
+    }
+    let path = generate_psk(&dir)?;
+    println!("wrote {}, share it with the other signers", path.display());
+    Ok(())
+}
+
+pub async fn relay(
+    args: RelayArgs,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), Box<dyn Error>> {
+    let password = keystore::read_password(args.password_file.as_deref(), "keystore password: ")?;
+    let keys = keystore::unlock(&args.keystore, &password)
+        .map_err(|e| format!("failed to unlock {}: {}", args.keystore.display(), e))?;
+
+    let mut limits = RelayLimits::default();
+    if let Some(max) = args.max_reservations {
+        limits.max_reservations = max;
+    }
+    if let Some(max) = args.max_circuits {
+        limits.max_circuits = max;
+    }
+    if let Some(secs) = args.max_circuit_duration {
+        limits.max_circuit_duration = Duration::from_secs(secs);
+    }
+    if let Some(bytes) = args.max_circuit_bytes {
+        limits.max_circuit_bytes = bytes;
+    }
+
+    let psk = get_psk(&args.psk_dir)?;
+    if psk.is_none() {
+        println!(
+            "no swarm.key in {}, relaying outside a private network",
+            args.psk_dir.display()
+        );
+    }
+
+    let mut relay = RelayServer::new(keys.identity, psk, limits)?;
+    for addr in &args.listen {
+        relay.listen_on(
+            addr.parse()
+                .map_err(|e| format!("invalid listen address {}: {}", addr, e))?,
+        )?;
+    }
+    for addr in &args.external {
+        relay.add_external_address(
+            addr.parse()
+                .map_err(|e| format!("invalid external address {}: {}", addr, e))?,
+        );
+    }
+    println!(
+        "signers reach this relay on <address>/p2p/{}",
+        relay.local_peer_id
+    );
+
+    relay.run(shutdown).await;
+    Ok(())
+}
+
+pub async fn register_peer(config: &Config) -> Result<(), Box<dyn Error>> {
+    let keys = unlock_keys(config)?;
+    let peer_id = PeerId::from(keys.identity.public()).to_string();
+
+    let (key, _) = signing_keys(config, &keys).await?;
+    let stellar = Client::with_key(key, config.stellar_network());
+    let tx_hash = stellar.register_peer_id(&peer_id).await?;
+
+    println!(
+        "registered peer id {} on {} in tx {}",
+        peer_id,
+        stellar.public_key().account_id(),
+        tx_hash
+    );
+    Ok(())
+}
+
+pub async fn signers(config: &Config) -> Result<(), Box<dyn Error>> {
+    let bridge = config.bridge();
+    let signers = bridge.node_signers().await?;
+
+    println!(
+        "signers of {} ({} signatures required):",
+        bridge.account, bridge.min_signatures
+    );
+    for signer in signers {
+        let peer_id = fetch_peer_id_from_account(signer.key.clone(), bridge.network.clone())
+            .await?
+            .unwrap_or_else(|| "not registered".to_string());
+        println!("  {} weight {}: {}", signer.key, signer.weight, peer_id);
+    }
+    Ok(())
+}
+
+// The node keeps its state in the store, which can be read while it is running.
+// Its network is only known to the running node, asked over the health server.
+pub async fn status(config: &Config) -> Result<(), Box<dyn Error>> {
+    let store = Store::open(&config.data_dir)?;
+
+    println!("horizon cursor: {:?}", store.horizon_cursor()?);
+    println!("last bsc block: {:?}", store.last_bsc_block()?);
+
+    println!("transfers:");
+    for (state, count) in store.transfer_counts()? {
+        println!("  {}: {}", state, count);
+    }
+
+    println!("pending:");
+    for transfer in store.pending_transfers()? {
+        println!(
+            "  {} {} {} (since {})",
+            transfer.kind, transfer.id, transfer.state, transfer.updated_at
+        );
+    }
+
+    match &config.health {
+        Some(health) => match health::fetch_status(health.listen).await {
+            Ok(status) => print_network(&status),
+            Err(e) => println!("network: node not reachable on {}: {}", health.listen, e),
+        },
+        None => println!("network: unknown, enable [health] to query the running node"),
+    }
+    Ok(())
+}
+
+fn print_network(status: &health::Status) {
+    println!("peer id: {}", status.peer_id);
+    println!("reachability: {}", status.reachability);
+
+    match &status.relay {
+        Some(relay) if relay.reserved => println!("relay: {} (reserved)", relay.peer_id),
+        Some(relay) => println!("relay: {} (no reservation)", relay.peer_id),
+        None => println!("relay: none"),
+    }
+
+    println!("listen addresses:");
+    for addr in &status.listen_addrs {
+        println!("  {}", addr);
+    }
+    println!("external addresses:");
+    for addr in &status.external_addrs {
+        println!("  {}", addr);
+    }
+
+    println!("connected peers:");
+    for peer in &status.connected_peers {
+        println!("  {} on {}", peer.peer_id, peer.addresses.join(", "));
+    }
+
+    println!("known peers:");
+    for peer in &status.known_peers {
+        println!("  {} ({})", peer.peer_id, peer.agent_version);
+        for addr in &peer.listen_addrs {
+            println!("    {}", addr);
+        }
+    }
+}
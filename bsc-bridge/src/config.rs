@@ -0,0 +1,558 @@
+use crate::{bridge::Bridge, remote_key::RemoteSigner};
+use serde::Deserialize;
+use std::{
+    env,
+    error::Error,
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+use tf_bsc::{
+    keys::KeyBackend,
+    types::{Address, U256},
+    BscError, Client as BscClient, TxType,
+};
+use tf_libp2p::{get_psk, multiaddr::Protocol, Multiaddr, WebsocketTls};
+use tf_stellar::{network::StellarNetwork, PublicKey};
+use thiserror::Error;
+use toml::{Table, Value};
+use url::Url;
+
+/// Environment variables starting with this prefix override the config file,
+/// `BSC_BRIDGE_BSC_RPC_URL` for instance sets `rpc_url` of the `[bsc]` section.
+pub const ENV_PREFIX: &str = "BSC_BRIDGE_";
+/// Environment variable holding the path of the config file itself
+pub const CONFIG_ENV: &str = "BSC_BRIDGE_CONFIG";
+/// Environment variable holding the format of the logs, a command line option
+pub const LOG_FORMAT_ENV: &str = "BSC_BRIDGE_LOG_FORMAT";
+pub const DEFAULT_CONFIG_FILE: &str = "bridge.toml";
+
+const SECTIONS: [&str; 7] = [
+    "stellar",
+    "bridge",
+    "bsc",
+    "p2p",
+    "keystore",
+    "remote_signer",
+    "health",
+];
+
+const TFT_ASSET_CODE: &str = "TFT";
+const TFT_TESTNET_ISSUER: &str = "GA47YZA3PKFUZMPLQ3B5F2E3CJIB57TGGU7SPCQT2WAEYKN766PWIMB3";
+const TFT_MAINNET_ISSUER: &str = "GBOVQKJYHXRR3DX6NOX2RRYFRCUMSADGDESTDNBDS6CDVLGVESRTAC47";
+const DEFAULT_CONFIRMATIONS: usize = 3;
+const GWEI: u64 = 1_000_000_000;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("failed to read config file {0}: {1}")]
+    Read(PathBuf, std::io::Error),
+    #[error("failed to parse config: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("invalid value for `{0}`: {1}")]
+    Invalid(&'static str, String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Directory of the bridge store
+    #[serde(default = "default_data_dir")]
+    pub data_dir: PathBuf,
+    /// Directory holding the swarm.key of the private network
+    #[serde(default = "default_psk_dir")]
+    pub psk_dir: PathBuf,
+    pub stellar: StellarConfig,
+    pub bridge: BridgeConfig,
+    pub bsc: BscConfig,
+    #[serde(default)]
+    pub p2p: P2pConfig,
+    #[serde(default)]
+    pub keystore: KeystoreConfig,
+    /// Signing service holding the Stellar and BSC keys instead of the keystore
+    pub remote_signer: Option<RemoteSignerConfig>,
+    /// HTTP server with the health probes, status and metrics of the node, disabled when not set
+    pub health: Option<HealthConfig>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkName {
+    Testnet,
+    Mainnet,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StellarConfig {
+    pub network: NetworkName,
+    /// Horizon instance to use instead of the public one of the network
+    pub horizon_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BridgeConfig {
+    /// Stellar account holding the deposited tokens
+    pub account: String,
+    #[serde(default = "default_asset_code")]
+    pub asset_code: String,
+    /// Defaults to the TFT issuer of the configured network
+    pub asset_issuer: Option<String>,
+    /// Signatures needed for a mint or a payout
+    pub min_signatures: usize,
+    /// Refuse the sign requests which aren't signed by a signer of the bridge account
+    #[serde(default)]
+    pub require_signed_requests: bool,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TxTypeName {
+    Legacy,
+    Eip1559,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BscConfig {
+    pub rpc_url: String,
+    pub contract_address: String,
+    pub chain_id: u64,
+    #[serde(default = "default_tx_type")]
+    pub tx_type: TxTypeName,
+    #[serde(default = "default_confirmations")]
+    pub confirmations: usize,
+    /// Don't submit transactions while the gas price is above this
+    pub max_gas_price_gwei: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct P2pConfig {
+    /// Relay to reserve a circuit on, so peers behind NAT can reach this node
+    pub relay: Option<String>,
+    /// More relays, the node moves on to the next one when the one in use fails
+    #[serde(default)]
+    pub relays: Vec<String>,
+    /// Addresses to listen on, a random TCP port on every interface when empty
+    #[serde(default)]
+    pub listen: Vec<String>,
+    /// Addresses to announce to the peers, like a port forwarded to this node
+    #[serde(default)]
+    pub external: Vec<String>,
+    /// Also connect over WebSocket, for hosts where only HTTP ports are open
+    #[serde(default)]
+    pub websocket: bool,
+    /// PEM certificate chain served on the /wss listen addresses
+    pub websocket_cert: Option<PathBuf>,
+    /// PEM private key of `websocket_cert`
+    pub websocket_key: Option<PathBuf>,
+    /// Also connect over QUIC, preferred with the peers which support it. Not
+    /// available in a private network
+    #[serde(default)]
+    pub quic: bool,
+    /// Find and connect to the other nodes on the local network, for local federations
+    #[serde(default)]
+    pub mdns: bool,
+    /// Only accept the nodes of the signers of the bridge account, as registered
+    /// in their "id" data entry, and the relays
+    #[serde(default)]
+    pub allowlist: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeystoreConfig {
+    /// Encrypted keystore holding the Stellar, BSC and libp2p keys of this signer
+    #[serde(default = "default_keystore_path")]
+    pub path: PathBuf,
+    /// File holding the keystore password, it is prompted for when not set
+    pub password_file: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteSignerConfig {
+    /// `http://host:port` or `unix:/path/of/the/socket`
+    pub url: String,
+    /// Name of the Stellar key at the signer
+    #[serde(default = "default_stellar_key")]
+    pub stellar_key: String,
+    /// Name of the BSC key at the signer
+    #[serde(default = "default_bsc_key")]
+    pub bsc_key: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HealthConfig {
+    /// Address to serve on, for instance 127.0.0.1:9100
+    pub listen: SocketAddr,
+}
+
+impl P2pConfig {
+    pub fn websocket_enabled(&self) -> bool {
+        self.websocket || self.websocket_cert.is_some()
+    }
+
+    /// Certificate and key of the /wss listen addresses, when configured.
+    pub fn websocket_tls(&self) -> Result<Option<WebsocketTls>, Box<dyn Error>> {
+        let (cert, key) = match (&self.websocket_cert, &self.websocket_key) {
+            (Some(cert), Some(key)) => (cert, key),
+            _ => return Ok(None),
+        };
+        let read = |path: &PathBuf| {
+            fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))
+        };
+        let tls = WebsocketTls::from_pem(&read(cert)?, &read(key)?)
+            .map_err(|e| format!("invalid websocket certificate: {}", e))?;
+        Ok(Some(tls))
+    }
+
+    fn validate_websocket(&self) -> Result<(), ConfigError> {
+        if self.websocket_cert.is_some() != self.websocket_key.is_some() {
+            return Err(invalid(
+                "p2p.websocket_cert",
+                "websocket_cert and websocket_key go together",
+            ));
+        }
+        for addr in &self.listen {
+            let addr: Multiaddr = match addr.parse() {
+                Ok(addr) => addr,
+                Err(_) => continue,
+            };
+            let ws = addr
+                .iter()
+                .any(|p| matches!(p, Protocol::Ws(_) | Protocol::Wss(_)));
+            let wss = addr.iter().any(|p| matches!(p, Protocol::Wss(_)));
+            if ws && !self.websocket_enabled() {
+                return Err(invalid(
+                    "p2p.listen",
+                    &format!("{} needs websocket = true", addr),
+                ));
+            }
+            if wss && self.websocket_cert.is_none() {
+                return Err(invalid(
+                    "p2p.listen",
+                    &format!("{} needs a websocket_cert and websocket_key", addr),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for KeystoreConfig {
+    fn default() -> Self {
+        KeystoreConfig {
+            path: default_keystore_path(),
+            password_file: None,
+        }
+    }
+}
+
+impl Config {
+    /// Read the config file at `path`, apply the environment overrides and validate the result.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let text =
+            fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_path_buf(), e))?;
+        let mut table: Table = toml::from_str(&text)?;
+        apply_env_overrides(&mut table, env::vars());
+
+        let config: Config = Value::Table(table).try_into()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(url) = &self.stellar.horizon_url {
+            check_url("stellar.horizon_url", url)?;
+        }
+
+        check_account("bridge.account", &self.bridge.account)?;
+        check_account("bridge.asset_issuer", &self.asset_issuer())?;
+        let code = &self.bridge.asset_code;
+        if code.is_empty() || code.len() > 12 || !code.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(invalid(
+                "bridge.asset_code",
+                "expected 1 to 12 alphanumeric characters",
+            ));
+        }
+        if self.bridge.min_signatures == 0 {
+            return Err(invalid("bridge.min_signatures", "must be at least 1"));
+        }
+
+        check_url("bsc.rpc_url", &self.bsc.rpc_url)?;
+        Address::from_str(&self.bsc.contract_address)
+            .map_err(|_| invalid("bsc.contract_address", "not a valid address"))?;
+        if self.bsc.chain_id == 0 {
+            return Err(invalid("bsc.chain_id", "must not be 0"));
+        }
+
+        if let Some(relay) = &self.p2p.relay {
+            check_relay("p2p.relay", relay)?;
+        }
+        for relay in &self.p2p.relays {
+            check_relay("p2p.relays", relay)?;
+        }
+        for addr in &self.p2p.listen {
+            check_multiaddr("p2p.listen", addr)?;
+        }
+        for addr in &self.p2p.external {
+            check_multiaddr("p2p.external", addr)?;
+        }
+        self.p2p.validate_websocket()?;
+        self.validate_quic()?;
+
+        if let Some(signer) = &self.remote_signer {
+            RemoteSigner::new(&signer.url)
+                .map_err(|e| invalid("remote_signer.url", &e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    // QUIC encrypts the connections with TLS on its own, they can't go through the
+    // pre shared key of a private network
+    fn validate_quic(&self) -> Result<(), ConfigError> {
+        for addr in &self.p2p.listen {
+            let quic = addr
+                .parse::<Multiaddr>()
+                .is_ok_and(|addr| addr.iter().any(|p| p == Protocol::QuicV1));
+            if quic && !self.p2p.quic {
+                return Err(invalid(
+                    "p2p.listen",
+                    &format!("{} needs quic = true", addr),
+                ));
+            }
+        }
+        if self.p2p.quic && matches!(get_psk(&self.psk_dir), Ok(Some(_))) {
+            return Err(invalid(
+                "p2p.quic",
+                &format!(
+                    "QUIC can't be used in a private network, turn it off or remove the swarm.key in {}",
+                    self.psk_dir.display()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Every configured relay, `p2p.relay` first.
+    pub fn relays(&self) -> impl Iterator<Item = &String> {
+        self.p2p.relay.iter().chain(&self.p2p.relays)
+    }
+
+    pub fn stellar_network(&self) -> StellarNetwork {
+        let network = match self.stellar.network {
+            NetworkName::Testnet => StellarNetwork::Testnet,
+            NetworkName::Mainnet => StellarNetwork::Mainnet,
+        };
+        match &self.stellar.horizon_url {
+            Some(url) => network.with_horizon_url(url.clone()),
+            None => network,
+        }
+    }
+
+    fn asset_issuer(&self) -> String {
+        match (&self.bridge.asset_issuer, self.stellar.network) {
+            (Some(issuer), _) => issuer.clone(),
+            (None, NetworkName::Testnet) => TFT_TESTNET_ISSUER.to_string(),
+            (None, NetworkName::Mainnet) => TFT_MAINNET_ISSUER.to_string(),
+        }
+    }
+
+    pub fn bridge(&self) -> Bridge {
+        Bridge {
+            account: self.bridge.account.clone(),
+            network: self.stellar_network(),
+            asset_code: self.bridge.asset_code.clone(),
+            asset_issuer: self.asset_issuer(),
+            min_signatures: self.bridge.min_signatures,
+            require_signed_requests: self.bridge.require_signed_requests,
+        }
+    }
+
+    /// Client signing with `key`, unlocked from the keystore or held by the remote signer.
+    pub fn bsc_client(&self, key: Arc<dyn KeyBackend>) -> Result<BscClient, BscError> {
+        let tx_type = match self.bsc.tx_type {
+            TxTypeName::Legacy => TxType::Legacy,
+            TxTypeName::Eip1559 => TxType::Eip1559,
+        };
+
+        let client = BscClient::with_key(
+            &self.bsc.rpc_url,
+            &self.bsc.contract_address,
+            self.bsc.chain_id,
+            key,
+            tx_type,
+        )?
+        .with_confirmations(self.bsc.confirmations);
+
+        Ok(match self.bsc.max_gas_price_gwei {
+            Some(gwei) => client.with_max_gas_price(U256::from(gwei) * GWEI),
+            None => client,
+        })
+    }
+}
+
+// Sets `BSC_BRIDGE_<SECTION>_<KEY>` as `key` of `[section]` and `BSC_BRIDGE_<KEY>` as top level `key`
+fn apply_env_overrides(table: &mut Table, vars: impl Iterator<Item = (String, String)>) {
+    for (name, raw) in vars {
+        // Read by the command line parser, they are no settings of the file
+        if name == CONFIG_ENV || name == LOG_FORMAT_ENV {
+            continue;
+        }
+        let name = match name.strip_prefix(ENV_PREFIX) {
+            Some(name) => name.to_lowercase(),
+            None => continue,
+        };
+
+        let section = SECTIONS.iter().find_map(|section| {
+            name.strip_prefix(section)
+                .and_then(|key| key.strip_prefix('_'))
+                .map(|key| (*section, key.to_string()))
+        });
+
+        let value = parse_env_value(&raw);
+        match section {
+            Some((section, key)) => {
+                let section = table
+                    .entry(section)
+                    .or_insert_with(|| Value::Table(Table::new()));
+                if let Value::Table(section) = section {
+                    section.insert(key, value);
+                }
+            }
+            None => {
+                table.insert(name, value);
+            }
+        }
+    }
+}
+
+// Decimal numbers, booleans and arrays are written as in TOML, anything else is a plain
+// string so hex encoded keys and addresses are not mistaken for numbers
+fn parse_env_value(raw: &str) -> Value {
+    let is_toml = raw.starts_with('[')
+        || raw == "true"
+        || raw == "false"
+        || (!raw.is_empty() && raw.chars().all(|c| c.is_ascii_digit()));
+    if !is_toml {
+        return Value::String(raw.to_string());
+    }
+
+    toml::from_str::<Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+fn check_url(field: &'static str, url: &str) -> Result<(), ConfigError> {
+    match Url::parse(url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Ok(()),
+        _ => Err(invalid(field, "expected an http or https url")),
+    }
+}
+
+fn check_account(field: &'static str, account: &str) -> Result<(), ConfigError> {
+    PublicKey::from_account_id(account)
+        .map(|_| ())
+        .map_err(|_| invalid(field, "not a valid Stellar account"))
+}
+
+fn check_multiaddr(field: &'static str, addr: &str) -> Result<(), ConfigError> {
+    addr.parse::<Multiaddr>()
+        .map(|_| ())
+        .map_err(|e| invalid(field, &format!("{}: {}", addr, e)))
+}
+
+// Reservations are made with the peer id of the relay
+fn check_relay(field: &'static str, addr: &str) -> Result<(), ConfigError> {
+    let parsed = addr
+        .parse::<Multiaddr>()
+        .map_err(|e| invalid(field, &format!("{}: {}", addr, e)))?;
+    match parsed.iter().last() {
+        Some(Protocol::P2p(_)) => Ok(()),
+        _ => Err(invalid(
+            field,
+            &format!("{} doesn't end with /p2p/<peer id>", addr),
+        )),
+    }
+}
+
+fn invalid(field: &'static str, reason: &str) -> ConfigError {
+    ConfigError::Invalid(field, reason.to_string())
+}
+
+fn default_data_dir() -> PathBuf {
+    PathBuf::from("data")
+}
+
+fn default_psk_dir() -> PathBuf {
+    PathBuf::from(".")
+}
+
+pub fn default_keystore_path() -> PathBuf {
+    PathBuf::from("data/keystore.json")
+}
+
+fn default_stellar_key() -> String {
+    "stellar".to_string()
+}
+
+fn default_bsc_key() -> String {
+    "bsc".to_string()
+}
+
+fn default_asset_code() -> String {
+    TFT_ASSET_CODE.to_string()
+}
+
+fn default_tx_type() -> TxTypeName {
+    TxTypeName::Legacy
+}
+
+fn default_confirmations() -> usize {
+    DEFAULT_CONFIRMATIONS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrides(vars: &[(&str, &str)]) -> Table {
+        let mut table = Table::new();
+        let vars = vars.iter().map(|(k, v)| (k.to_string(), v.to_string()));
+        apply_env_overrides(&mut table, vars);
+        table
+    }
+
+    #[test]
+    fn env_overrides_set_section_and_top_level_keys() {
+        let table = overrides(&[
+            ("BSC_BRIDGE_BSC_RPC_URL", "https://bsc.example"),
+            ("BSC_BRIDGE_BSC_CHAIN_ID", "56"),
+            ("BSC_BRIDGE_STATE_DIR", "/var/lib/bridge"),
+            ("OTHER_VAR", "ignored"),
+        ]);
+
+        assert_eq!(
+            table["bsc"]["rpc_url"],
+            Value::String("https://bsc.example".into())
+        );
+        assert_eq!(table["bsc"]["chain_id"], Value::Integer(56));
+        assert_eq!(table["state_dir"], Value::String("/var/lib/bridge".into()));
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn env_overrides_skip_command_line_variables() {
+        let table = overrides(&[(CONFIG_ENV, "bridge.toml"), (LOG_FORMAT_ENV, "json")]);
+
+        assert!(table.is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+use crate::store::Store;
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Client, Method, Request, Response, Server, StatusCode, Uri,
+};
+use prometheus_client::{encoding::text::encode, registry::Registry};
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, error::Error, net::SocketAddr, sync::Arc, time::Duration};
+use tf_libp2p::{Handler, NatStatus, PeerId};
+use tokio::time;
+use tracing::info;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the health server reports on, shared with the rest of the node.
+pub struct Health {
+    pub handler: Handler,
+    pub store: Arc<Store>,
+    pub peer_id: PeerId,
+    pub stellar_address: String,
+    pub bsc_address: String,
+    /// Signatures needed for a transfer, this node signs one of them itself
+    pub min_signatures: usize,
+    pub registry: Registry,
+}
+
+/// Body of `/status`, also read back by the `status` command.
+#[derive(Serialize, Deserialize)]
+pub struct Status {
+    pub peer_id: String,
+    pub stellar_address: String,
+    pub bsc_address: String,
+    pub connected_peers: Vec<ConnectedPeer>,
+    pub relay: Option<RelayStatus>,
+    /// public, private or unknown, as found by AutoNAT
+    pub reachability: String,
+    pub listen_addrs: Vec<String>,
+    pub external_addrs: Vec<String>,
+    pub known_peers: Vec<KnownPeer>,
+    pub horizon_cursor: Option<String>,
+    pub last_bsc_block: Option<u64>,
+    pub pending_transfers: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ConnectedPeer {
+    pub peer_id: String,
+    pub addresses: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RelayStatus {
+    pub peer_id: String,
+    pub reserved: bool,
+}
+
+/// A peer which identified itself, connected or not.
+#[derive(Serialize, Deserialize)]
+pub struct KnownPeer {
+    pub peer_id: String,
+    pub agent_version: String,
+    pub listen_addrs: Vec<String>,
+}
+
+impl Health {
+    async fn status(&self) -> Result<Status, Box<dyn Error>> {
+        let peers = self.handler.peers().await?;
+        let relay = self.handler.relay_status().await?;
+        let network = self.handler.status().await?;
+        let listen_addrs = self.handler.listen_addrs().await?;
+        let external_addrs = self.handler.external_addrs().await?;
+        let known_peers = self.handler.known_peers().await?;
+
+        Ok(Status {
+            peer_id: self.peer_id.to_string(),
+            stellar_address: self.stellar_address.clone(),
+            bsc_address: self.bsc_address.clone(),
+            connected_peers: peers
+                .into_iter()
+                .map(|peer| ConnectedPeer {
+                    peer_id: peer.peer_id.to_string(),
+                    addresses: strings(&peer.addresses),
+                })
+                .collect(),
+            relay: relay.relay.map(|peer_id| RelayStatus {
+                peer_id: peer_id.to_string(),
+                reserved: relay.reserved,
+            }),
+            reachability: match network.nat_status {
+                NatStatus::Public(_) => "public",
+                NatStatus::Private => "private",
+                NatStatus::Unknown => "unknown",
+            }
+            .to_string(),
+            listen_addrs: strings(&listen_addrs),
+            external_addrs: strings(&external_addrs),
+            known_peers: known_peers
+                .into_iter()
+                .map(|peer| KnownPeer {
+                    peer_id: peer.peer_id.to_string(),
+                    agent_version: peer.agent_version,
+                    listen_addrs: strings(&peer.listen_addrs),
+                })
+                .collect(),
+            horizon_cursor: self.store.horizon_cursor()?,
+            last_bsc_block: self.store.last_bsc_block()?,
+            pending_transfers: self.store.pending_transfers()?.len(),
+        })
+    }
+
+    // Alive as long as the swarm loop answers, the node is useless without it
+    async fn live(&self) -> Result<(), String> {
+        self.handler
+            .status()
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    // Ready when it can read its store and reach enough signers to collect signatures
+    async fn ready(&self) -> Result<(), String> {
+        let status = self.status().await.map_err(|e| e.to_string())?;
+
+        let needed = self.min_signatures.saturating_sub(1);
+        if status.connected_peers.len() < needed {
+            return Err(format!(
+                "connected to {} peers, {} needed",
+                status.connected_peers.len(),
+                needed
+            ));
+        }
+        // A publicly reachable node doesn't hold a reservation
+        if let Some(relay) = &status.relay {
+            if !relay.reserved && status.reachability != "public" {
+                return Err(format!("no reservation on relay {}", relay.peer_id));
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        if request.method() != Method::GET {
+            return text(StatusCode::METHOD_NOT_ALLOWED, "method not allowed");
+        }
+
+        match request.uri().path() {
+            "/livez" => probe(self.live().await),
+            "/readyz" => probe(self.ready().await),
+            "/status" => match self.status().await {
+                Ok(status) => match serde_json::to_vec(&status) {
+                    Ok(json) => Response::builder()
+                        .header(CONTENT_TYPE, "application/json")
+                        .body(Body::from(json))
+                        .unwrap_or_default(),
+                    Err(e) => text(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+                },
+                Err(e) => text(StatusCode::SERVICE_UNAVAILABLE, &e.to_string()),
+            },
+            "/metrics" => self.metrics(),
+            _ => text(StatusCode::NOT_FOUND, "not found"),
+        }
+    }
+
+    fn metrics(&self) -> Response<Body> {
+        let mut body = String::new();
+        if let Err(e) = encode(&mut body, &self.registry) {
+            return text(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string());
+        }
+        Response::builder()
+            .header(
+                CONTENT_TYPE,
+                "application/openmetrics-text; version=1.0.0; charset=utf-8",
+            )
+            .body(Body::from(body))
+            .unwrap_or_default()
+    }
+}
+
+/// Serve `/livez`, `/readyz`, `/status` and `/metrics` on `addr` until the node stops.
+pub async fn serve(addr: SocketAddr, health: Arc<Health>) -> Result<(), hyper::Error> {
+    let make_service = make_service_fn(move |_| {
+        let health = health.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let health = health.clone();
+                async move { Ok::<_, Infallible>(health.handle(request).await) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    info!("serving health probes on http://{}", addr);
+    server.await
+}
+
+/// Ask the node serving health on `addr` for its status.
+pub async fn fetch_status(addr: SocketAddr) -> Result<Status, Box<dyn Error>> {
+    let uri: Uri = format!("http://{}/status", addr).parse()?;
+    let response = time::timeout(FETCH_TIMEOUT, Client::new().get(uri))
+        .await
+        .map_err(|_| "timed out")??;
+
+    let status = response.status();
+    let bytes = hyper::body::to_bytes(response.into_body()).await?;
+    if !status.is_success() {
+        return Err(format!("{}: {}", status, String::from_utf8_lossy(&bytes).trim()).into());
+    }
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+fn strings<T: ToString>(items: &[T]) -> Vec<String> {
+    items.iter().map(ToString::to_string).collect()
+}
+
+fn probe(result: Result<(), String>) -> Response<Body> {
+    match result {
+        Ok(()) => text(StatusCode::OK, "ok"),
+        Err(reason) => text(StatusCode::SERVICE_UNAVAILABLE, &reason),
+    }
+}
+
+fn text(status: StatusCode, body: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain")
+        .body(Body::from(format!("{}\n", body)))
+        .unwrap_or_default()
+}
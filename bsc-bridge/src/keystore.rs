@@ -0,0 +1,325 @@
+use base64::{engine::general_purpose, Engine as _};
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use serde::{Deserialize, Serialize};
+use std::{fs, io::Write, path::Path, str::FromStr};
+use tf_bsc::types::H256;
+use tf_libp2p::{create_private_file, Keypair};
+use tf_stellar::{network::StellarNetwork, Client};
+use thiserror::Error;
+
+const VERSION: u32 = 1;
+const KDF: &str = "scrypt";
+const CIPHER: &str = "xchacha20poly1305";
+
+// Costs about 32MB of memory and a fraction of a second to unlock
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+const SALT_LEN: usize = 32;
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+
+#[derive(Error, Debug)]
+pub enum KeystoreError {
+    #[error("failed to access keystore: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid keystore: {0}")]
+    Format(String),
+    #[error("unsupported keystore, {0}")]
+    Unsupported(String),
+    #[error("wrong password or corrupted keystore")]
+    Decrypt,
+    #[error("invalid {0} key")]
+    InvalidKey(&'static str),
+}
+
+/// The keys of a signer, held encrypted in the keystore. The Stellar and BSC keys
+/// are left out when they are held by a remote signer.
+pub struct Keys {
+    /// Secret seed of the Stellar signer of the bridge account
+    pub stellar_secret: Option<String>,
+    /// Hex encoded secp256k1 key of the BSC signer
+    pub bsc_secret: Option<String>,
+    /// Identity of the node in the libp2p network
+    pub identity: Keypair,
+}
+
+impl Keys {
+    fn validate(&self) -> Result<(), KeystoreError> {
+        if let Some(secret) = &self.stellar_secret {
+            // The network doesn't matter, only the seed is checked
+            Client::new(secret, StellarNetwork::Testnet)
+                .map_err(|_| KeystoreError::InvalidKey("stellar"))?;
+        }
+        if let Some(secret) = &self.bsc_secret {
+            H256::from_str(secret).map_err(|_| KeystoreError::InvalidKey("bsc"))?;
+        }
+        Ok(())
+    }
+}
+
+// Content of the keystore file, everything but the kdf parameters is encrypted
+#[derive(Serialize, Deserialize)]
+struct KeystoreFile {
+    version: u32,
+    kdf: KdfParams,
+    cipher: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KdfParams {
+    name: String,
+    log_n: u8,
+    r: u32,
+    p: u32,
+    salt: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Secrets {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stellar_secret: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bsc_secret: Option<String>,
+    /// Protobuf encoded libp2p keypair
+    identity: String,
+}
+
+/// Encrypt `keys` with `password` and write them to a new keystore at `path`,
+/// an existing keystore is never overwritten.
+pub fn create(path: &Path, keys: &Keys, password: &str) -> Result<(), KeystoreError> {
+    keys.validate()?;
+
+    let identity = keys
+        .identity
+        .to_protobuf_encoding()
+        .map_err(|_| KeystoreError::InvalidKey("libp2p"))?;
+    let secrets = Secrets {
+        stellar_secret: keys.stellar_secret.clone(),
+        bsc_secret: keys.bsc_secret.clone(),
+        identity: general_purpose::STANDARD.encode(identity),
+    };
+    let plaintext =
+        serde_json::to_vec(&secrets).map_err(|e| KeystoreError::Format(e.to_string()))?;
+
+    let salt: [u8; SALT_LEN] = rand::random();
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let kdf = KdfParams {
+        name: KDF.to_string(),
+        log_n: SCRYPT_LOG_N,
+        r: SCRYPT_R,
+        p: SCRYPT_P,
+        salt: general_purpose::STANDARD.encode(salt),
+    };
+
+    let key = derive_key(password, &kdf, &salt)?;
+    let ciphertext = XChaCha20Poly1305::new(Key::from_slice(&key))
+        .encrypt(XNonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| KeystoreError::Format("failed to encrypt".to_string()))?;
+
+    let file = KeystoreFile {
+        version: VERSION,
+        kdf,
+        cipher: CIPHER.to_string(),
+        nonce: general_purpose::STANDARD.encode(nonce),
+        ciphertext: general_purpose::STANDARD.encode(ciphertext),
+    };
+    let json =
+        serde_json::to_vec_pretty(&file).map_err(|e| KeystoreError::Format(e.to_string()))?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    create_private_file(path)?.write_all(&json)?;
+    Ok(())
+}
+
+/// Read the keystore at `path` and decrypt it with `password`.
+pub fn unlock(path: &Path, password: &str) -> Result<Keys, KeystoreError> {
+    let json = fs::read(path)?;
+    let file: KeystoreFile =
+        serde_json::from_slice(&json).map_err(|e| KeystoreError::Format(e.to_string()))?;
+
+    if file.version != VERSION {
+        return Err(KeystoreError::Unsupported(format!(
+            "version {}",
+            file.version
+        )));
+    }
+    if file.cipher != CIPHER {
+        return Err(KeystoreError::Unsupported(format!(
+            "cipher {}",
+            file.cipher
+        )));
+    }
+
+    let salt = decode("kdf.salt", &file.kdf.salt)?;
+    let nonce = decode("nonce", &file.nonce)?;
+    let ciphertext = decode("ciphertext", &file.ciphertext)?;
+    if nonce.len() != NONCE_LEN {
+        return Err(KeystoreError::Format("invalid nonce length".to_string()));
+    }
+
+    let key = derive_key(password, &file.kdf, &salt)?;
+    let plaintext = XChaCha20Poly1305::new(Key::from_slice(&key))
+        .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| KeystoreError::Decrypt)?;
+
+    let secrets: Secrets =
+        serde_json::from_slice(&plaintext).map_err(|e| KeystoreError::Format(e.to_string()))?;
+    let identity = Keypair::from_protobuf_encoding(&decode("identity", &secrets.identity)?)
+        .map_err(|_| KeystoreError::InvalidKey("libp2p"))?;
+
+    let keys = Keys {
+        stellar_secret: secrets.stellar_secret,
+        bsc_secret: secrets.bsc_secret,
+        identity,
+    };
+    keys.validate()?;
+    Ok(keys)
+}
+
+/// Read the keystore password from `file`, or prompt for it when no file is given.
+pub fn read_password(file: Option<&Path>, prompt: &str) -> std::io::Result<String> {
+    match file {
+        Some(file) => {
+            let password = fs::read_to_string(file)?;
+            Ok(password.trim_end_matches(&['\r', '\n'][..]).to_string())
+        }
+        None => rpassword::prompt_password(prompt),
+    }
+}
+
+fn derive_key(
+    password: &str,
+    kdf: &KdfParams,
+    salt: &[u8],
+) -> Result<[u8; KEY_LEN], KeystoreError> {
+    if kdf.name != KDF {
+        return Err(KeystoreError::Unsupported(format!("kdf {}", kdf.name)));
+    }
+    let params = scrypt::Params::new(kdf.log_n, kdf.r, kdf.p)
+        .map_err(|_| KeystoreError::Format("invalid scrypt parameters".to_string()))?;
+
+    let mut key = [0u8; KEY_LEN];
+    scrypt::scrypt(password.as_bytes(), salt, &params, &mut key)
+        .map_err(|_| KeystoreError::Format("invalid key length".to_string()))?;
+    Ok(key)
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, KeystoreError> {
+    general_purpose::STANDARD
+        .decode(value)
+        .map_err(|_| KeystoreError::Format(format!("{} is not valid base64", field)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // A fresh directory per test, removed again when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "bsc-bridge-keystore-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            TempDir(dir)
+        }
+
+        fn keystore(&self) -> PathBuf {
+            self.0.join("keystore.json")
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn keys() -> Keys {
+        Keys {
+            stellar_secret: Some(tf_stellar::generate_secret().unwrap()),
+            bsc_secret: Some(tf_bsc::generate_key().0),
+            identity: Keypair::generate_ed25519(),
+        }
+    }
+
+    #[test]
+    fn unlock_returns_the_created_keys() {
+        let dir = TempDir::new("round-trip");
+        let keys = keys();
+        create(&dir.keystore(), &keys, "secret").unwrap();
+
+        let unlocked = unlock(&dir.keystore(), "secret").unwrap();
+        assert_eq!(unlocked.stellar_secret, keys.stellar_secret);
+        assert_eq!(unlocked.bsc_secret, keys.bsc_secret);
+        assert_eq!(unlocked.identity.public(), keys.identity.public());
+    }
+
+    #[test]
+    fn remote_keys_are_left_out() {
+        let dir = TempDir::new("remote");
+        let keys = Keys {
+            stellar_secret: None,
+            bsc_secret: None,
+            identity: Keypair::generate_ed25519(),
+        };
+        create(&dir.keystore(), &keys, "secret").unwrap();
+
+        let unlocked = unlock(&dir.keystore(), "secret").unwrap();
+        assert_eq!(unlocked.stellar_secret, None);
+        assert_eq!(unlocked.bsc_secret, None);
+        assert_eq!(unlocked.identity.public(), keys.identity.public());
+    }
+
+    #[test]
+    fn wrong_password_fails_to_decrypt() {
+        let dir = TempDir::new("wrong-password");
+        create(&dir.keystore(), &keys(), "secret").unwrap();
+
+        assert!(matches!(
+            unlock(&dir.keystore(), "not the secret"),
+            Err(KeystoreError::Decrypt)
+        ));
+    }
+
+    #[test]
+    fn existing_keystore_is_not_overwritten() {
+        let dir = TempDir::new("overwrite");
+        create(&dir.keystore(), &keys(), "secret").unwrap();
+
+        assert!(matches!(
+            create(&dir.keystore(), &keys(), "other"),
+            Err(KeystoreError::Io(e)) if e.kind() == std::io::ErrorKind::AlreadyExists
+        ));
+        assert!(unlock(&dir.keystore(), "secret").is_ok());
+    }
+
+    #[test]
+    fn invalid_keys_are_rejected() {
+        let dir = TempDir::new("invalid");
+        let keys = Keys {
+            bsc_secret: Some("not hex".to_string()),
+            ..keys()
+        };
+
+        assert!(matches!(
+            create(&dir.keystore(), &keys, "secret"),
+            Err(KeystoreError::InvalidKey("bsc"))
+        ));
+        assert!(!dir.keystore().exists());
+    }
+}
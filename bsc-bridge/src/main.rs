@@ -1,56 +1,207 @@
-use pretty_env_logger;
-use std::env;
-use std::path::Path;
-use tf_libp2p::{
-    get_psk,
-    traits::{Signer, SignerError},
-    types::{SignRequest, SignResponse},
-    Libp2pHost,
+use clap::Parser;
+use cli::{Cli, Command, LogFormat};
+use config::Config;
+use health::Health;
+use metrics::BridgeMetrics;
+use orchestrator::Orchestrator;
+use signer::TxSigner;
+use std::{
+    error::Error,
+    future,
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
 };
-use tf_stellar::{fetch_peer_id_from_account, network::StellarNetwork, Client};
+use store::Store;
+use tf_libp2p::{get_psk, Libp2pHost, PeerId, Registry};
+use tf_stellar::Client;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
+};
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
+
+mod allowlist;
+mod bridge;
+mod cli;
+mod config;
+mod health;
+mod keystore;
+mod metrics;
+mod mint;
+mod orchestrator;
+mod remote_key;
+mod signer;
+mod store;
+mod transfer;
+mod withdraw;
 
-pub struct TxSigner {}
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    init_logging(cli.log_format);
 
-impl Signer for TxSigner {
-    fn sign(&self, _message: &SignRequest) -> Result<SignResponse, SignerError> {
-        Ok(vec![])
+    if let Err(e) = execute(cli).await {
+        error!("{}", e);
+        process::exit(1);
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    pretty_env_logger::init();
-
-    let args: Vec<String> = env::args().collect();
-
-    let stellar_secret = &args[1];
-    let relay_addr = &args[2];
-
-    // let kp = ed25519::SecretKey::from_bytes(stellar_secret.as_bytes().try_into().unwrap());
+// RUST_LOG filters the logs as before, everything from info up is logged when it is not set
+fn init_logging(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let logs = tracing_subscriber::fmt().with_env_filter(filter);
+    match format {
+        LogFormat::Text => logs.init(),
+        // Each line carries the fields of its spans, like the transfer id and the peer
+        LogFormat::Json => logs.json().with_span_list(true).init(),
+    }
+}
 
-    // let s = "SAN72MXJM3APLG3IPBFE4SO3WC7USSHFGJQKTUO7X66UIF4DAHLPV23L"
-    // let peer key = "f4ded75a662ed6b140d1354964f24f1ed3db986e96c99827f242940b049b36d3"
+async fn execute(cli: Cli) -> Result<(), Box<dyn Error>> {
+    match cli.command {
+        Command::Run => run(load_config(&cli)?).await,
+        Command::Keygen {
+            keystore,
+            password_file,
+            import_stellar,
+            identity_only,
+        } => {
+            let keystore = match keystore {
+                Some(keystore) => keystore,
+                None => keystore_path(&cli.config)?,
+            };
+            cli::keygen(keystore, password_file, import_stellar, identity_only)
+        }
+        Command::Psk { dir } => cli::psk(dir),
+        Command::RegisterPeer => cli::register_peer(&load_config(&cli)?).await,
+        Command::Signers => cli::signers(&load_config(&cli)?).await,
+        Command::Status => cli::status(&load_config(&cli)?).await,
+        Command::Relay(args) => {
+            let shutdown = async {
+                match shutdown_signal().await {
+                    Ok(()) => info!("received shutdown signal"),
+                    // Nothing to stop it then, keep relaying
+                    Err(e) => {
+                        error!("failed to listen for shutdown signals: {}", e);
+                        future::pending().await
+                    }
+                }
+            };
+            cli::relay(args, shutdown).await
+        }
+    }
+}
 
-    let peer_1_id = fetch_peer_id_from_account(
-        "GDU22QMFGQVYYGAKD3UQQUFZVPUZMOKHNNZV3U4TOPOGW545GQS4R2IR".to_string(),
-        StellarNetwork::Testnet,
-    )
-    .await?;
+fn load_config(cli: &Cli) -> Result<Config, Box<dyn Error>> {
+    Config::load(&cli.config)
+        .map_err(|e| format!("invalid config {}: {}", cli.config.display(), e).into())
+}
 
-    println!("peer id: {:?}", peer_1_id);
+// The keystore `run` unlocks, keys may be created before the config file is written
+fn keystore_path(config_file: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    if !config_file.exists() {
+        return Ok(config::default_keystore_path());
+    }
+    let config = Config::load(config_file)
+        .map_err(|e| format!("invalid config {}: {}", config_file.display(), e))?;
+    Ok(config.keystore.path)
+}
 
-    let _ = Client::new(&stellar_secret, StellarNetwork::Testnet)?;
+async fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    let keys = cli::unlock_keys(&config)?;
+    let (stellar_key, bsc_key) = cli::signing_keys(&config, &keys).await?;
+
+    let bridge = config.bridge();
+    let stellar = Arc::new(Client::with_key(stellar_key, bridge.network.clone()));
+    let bsc = Arc::new(config.bsc_client(bsc_key)?);
+
+    let store = Arc::new(Store::open(&config.data_dir)?);
+    info!(
+        "starting from horizon cursor {:?} and bsc block {:?}",
+        store.horizon_cursor()?,
+        store.last_bsc_block()?
+    );
+
+    let psk = get_psk(&config.psk_dir)?;
+
+    let peer_id = PeerId::from(keys.identity.public());
+    let signer = TxSigner::new(bsc.clone(), stellar.clone(), bridge.clone());
+    let mut registry = Registry::default();
+    let mut builder = Libp2pHost::builder(signer)
+        .keypair(keys.identity)
+        .psk(psk)
+        .websocket(config.p2p.websocket_enabled())
+        .quic(config.p2p.quic)
+        .mdns(config.p2p.mdns);
+    if let Some(tls) = config.p2p.websocket_tls()? {
+        builder = builder.websocket_tls(tls);
+    }
+    for addr in &config.p2p.listen {
+        builder = builder.listen_on(addr.parse()?);
+    }
+    for addr in &config.p2p.external {
+        builder = builder.external_address(addr.parse()?);
+    }
+    let mut host = builder.build()?.with_metrics(&mut registry);
+    let metrics = BridgeMetrics::new(&mut registry);
 
-    let psk = get_psk(&Path::new("."))?;
+    for relay in config.relays() {
+        host.connect_to_relay(relay.to_string()).await?;
+    }
 
-    let signer = TxSigner {};
-    let mut host = Libp2pHost::new(None, psk, signer).await?;
+    let handler = host.run();
 
-    host.connect_to_relay(relay_addr.to_string()).await?;
+    if let Some(health) = &config.health {
+        let state = Arc::new(Health {
+            handler: handler.clone(),
+            store: store.clone(),
+            peer_id,
+            stellar_address: stellar.public_key().account_id(),
+            bsc_address: format!("{:?}", bsc.address()),
+            min_signatures: bridge.min_signatures,
+            registry,
+        });
+        let addr = health.listen;
+        tokio::spawn(async move {
+            if let Err(e) = health::serve(addr, state).await {
+                error!("health server stopped: {}", e);
+            }
+        });
+    }
 
-    let handler = host.run();
+    let (stop, stopped) = watch::channel(false);
+    tokio::spawn(async move {
+        match shutdown_signal().await {
+            Ok(()) => {
+                info!("received shutdown signal, finishing the current step");
+                let _ = stop.send(true);
+            }
+            Err(e) => error!("failed to listen for shutdown signals: {}", e),
+        }
+    });
+
+    if config.p2p.allowlist {
+        tokio::spawn(allowlist::run(
+            handler.clone(),
+            bridge.clone(),
+            stopped.clone(),
+        ));
+    }
 
-    let _repsonses = handler.send(SignRequest::MintRequest(vec![]), 4).await?;
+    let result = Orchestrator::new(handler.clone(), bsc, stellar, store, bridge, metrics)
+        .run(stopped)
+        .await;
+    handler.shutdown().await?;
+    result
+}
 
-    Ok(())
+// Resolves on the first SIGINT or SIGTERM
+async fn shutdown_signal() -> std::io::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result,
+        _ = terminate.recv() => Ok(()),
+    }
 }
@@ -0,0 +1,136 @@
+use crate::{
+    store::{StoreError, Transfer},
+    transfer::{TransferKind, TransferState},
+    withdraw::Payout,
+};
+use prometheus_client::{
+    encoding::EncodeLabelSet,
+    metrics::{counter::Counter, family::Family},
+    registry::Registry,
+};
+use std::error::Error;
+use tf_bsc::{mint::MintParams, BscError};
+use tf_stellar::HorizonError;
+
+/// Where a failure of the orchestrator came from.
+#[derive(Debug, Clone, Copy)]
+pub enum ErrorSource {
+    Horizon,
+    Bsc,
+    Store,
+    Other,
+}
+
+impl ErrorSource {
+    /// Source of `e`, or `fallback` when the error type doesn't tell.
+    pub fn of(e: &(dyn Error + 'static), fallback: ErrorSource) -> ErrorSource {
+        if e.is::<HorizonError>() {
+            ErrorSource::Horizon
+        } else if e.is::<BscError>() {
+            ErrorSource::Bsc
+        } else if e.is::<StoreError>() {
+            ErrorSource::Store
+        } else {
+            fallback
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            ErrorSource::Horizon => "horizon",
+            ErrorSource::Bsc => "bsc",
+            ErrorSource::Store => "store",
+            ErrorSource::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ErrorLabels {
+    source: String,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct TransferLabels {
+    kind: String,
+    state: String,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct DirectionLabels {
+    direction: String,
+}
+
+/// Metrics of the transfers going through the bridge.
+#[derive(Clone, Default)]
+pub struct BridgeMetrics {
+    errors: Family<ErrorLabels, Counter>,
+    transfers: Family<TransferLabels, Counter>,
+    bridged: Family<DirectionLabels, Counter>,
+}
+
+impl BridgeMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let metrics = BridgeMetrics::default();
+
+        let registry = registry.sub_registry_with_prefix("bridge");
+        registry.register(
+            "errors",
+            "Failed polls and transfer steps, by source of the failure",
+            metrics.errors.clone(),
+        );
+        registry.register(
+            "transfers_processed",
+            "Transfers which reached a final state, by kind and state",
+            metrics.transfers.clone(),
+        );
+        registry.register(
+            "bridged_stroops",
+            "Amount of tokens moved over the bridge in stroops, by direction",
+            metrics.bridged.clone(),
+        );
+        metrics
+    }
+
+    pub fn error(&self, source: ErrorSource) {
+        self.errors
+            .get_or_create(&ErrorLabels {
+                source: source.as_str().to_string(),
+            })
+            .inc();
+    }
+
+    /// Record `transfer` reaching the final state `state`.
+    pub fn processed(&self, transfer: &Transfer, state: TransferState) {
+        self.transfers
+            .get_or_create(&TransferLabels {
+                kind: transfer.kind.to_string(),
+                state: state.to_string(),
+            })
+            .inc();
+
+        let (direction, amount) = match (transfer.kind, state) {
+            (TransferKind::Deposit, TransferState::Confirmed) => (
+                "stellar_to_bsc",
+                bson::from_slice::<MintParams>(&transfer.payload).map(|p| p.amount.low_u64()),
+            ),
+            (TransferKind::Withdraw, TransferState::Confirmed) => (
+                "bsc_to_stellar",
+                bson::from_slice::<Payout>(&transfer.payload).map(|p| p.amount() as u64),
+            ),
+            (TransferKind::Deposit, TransferState::Refunded) => (
+                "refund",
+                bson::from_slice::<Payout>(&transfer.payload).map(|p| p.amount() as u64),
+            ),
+            _ => return,
+        };
+
+        if let Ok(amount) = amount {
+            self.bridged
+                .get_or_create(&DirectionLabels {
+                    direction: direction.to_string(),
+                })
+                .inc_by(amount);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{bridge::Bridge, withdraw::RefundParams};
+use base64::{engine::general_purpose, Engine as _};
+use std::error::Error;
+use tf_bsc::{
+    mint::MintParams,
+    types::{Address, U256},
+};
+use tf_stellar::payments::{fetch_payments_for_transaction, Payment};
+
+// A deposit memo is a hash memo holding the BSC address, left padded with zeroes
+const ADDRESS_OFFSET: usize = 12;
+
+/// What a payment to the bridge account means for the bridge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Deposit {
+    /// Not a deposit of the bridged asset, nothing to do
+    Ignored,
+    Valid(MintParams),
+    /// Deposit of the bridged asset which can't be minted, to be paid back
+    Invalid(RefundParams),
+}
+
+pub fn classify(bridge: &Bridge, payment: &Payment) -> Deposit {
+    if !payment.is_payment() || !payment.transaction_successful {
+        return Deposit::Ignored;
+    }
+    if payment.to.as_deref() != Some(bridge.account.as_str()) {
+        return Deposit::Ignored;
+    }
+    if payment.asset_code.as_deref() != Some(bridge.asset_code.as_str())
+        || payment.asset_issuer.as_deref() != Some(bridge.asset_issuer.as_str())
+    {
+        return Deposit::Ignored;
+    }
+
+    let (sender, amount) = match (&payment.from, payment.stroops()) {
+        (Some(sender), Some(amount)) if amount > 0 && *sender != bridge.account => {
+            (sender.clone(), amount)
+        }
+        _ => return Deposit::Ignored,
+    };
+
+    match receiver_from_memo(payment) {
+        Some(receiver) => Deposit::Valid(MintParams {
+            receiver,
+            amount: U256::from(amount),
+            tx_id: payment.transaction_hash.clone(),
+        }),
+        None => Deposit::Invalid(RefundParams {
+            deposit_tx_hash: payment.transaction_hash.clone(),
+            receiver: sender,
+            amount,
+            tx: None,
+        }),
+    }
+}
+
+/// Check that `params` match a valid deposit on Stellar.
+pub async fn verify(bridge: &Bridge, params: &MintParams) -> Result<bool, Box<dyn Error>> {
+    let payments =
+        fetch_payments_for_transaction(params.tx_id.clone(), bridge.network.clone()).await?;
+
+    Ok(payments
+        .iter()
+        .any(|p| classify(bridge, p) == Deposit::Valid(params.clone())))
+}
+
+fn receiver_from_memo(payment: &Payment) -> Option<Address> {
+    let tx = payment.transaction.as_ref()?;
+    if tx.memo_type != "hash" {
+        return None;
+    }
+
+    let memo = general_purpose::STANDARD.decode(tx.memo.as_ref()?).ok()?;
+    if memo.len() != ADDRESS_OFFSET + Address::len_bytes()
+        || memo[..ADDRESS_OFFSET].iter().any(|b| *b != 0)
+    {
+        return None;
+    }
+
+    let receiver = Address::from_slice(&memo[ADDRESS_OFFSET..]);
+    if receiver.is_zero() {
+        return None;
+    }
+    Some(receiver)
+}
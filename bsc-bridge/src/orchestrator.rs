@@ -0,0 +1,622 @@
+use crate::{
+    bridge::Bridge,
+    metrics::{BridgeMetrics, ErrorSource},
+    mint::{classify, Deposit},
+    store::{now, Store, Transfer},
+    transfer::{TransferKind, TransferState},
+    withdraw::{is_expired, Payout, WithdrawParams},
+};
+use std::{collections::HashSet, error::Error, hash::Hash, sync::Arc, time::Duration};
+use tf_bsc::{
+    mint::{MintOutcome, MintParams},
+    types::Address,
+    withdraw::Withdraw,
+    Client as BscClient,
+};
+use tf_libp2p::{
+    event::HostEvent,
+    types::{RequestSignature, SignRequest, SignedRequest},
+    Handler,
+};
+use tf_stellar::{
+    payments::{fetch_latest_payment_cursor, fetch_payments, Payment},
+    transaction::{
+        decode_signature, encode_signature, fetch_sequence, submit_transaction, verify_signature,
+    },
+    Client, PublicKey, Transaction,
+};
+use tokio::{sync::watch, time};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+// Transfers which didn't change state for this long are reported as stuck
+const STUCK_AFTER: i64 = 10 * 60;
+// Maximum amount of blocks queried for withdraw events at once
+const MAX_BLOCK_RANGE: u64 = 5000;
+
+/// Drives every transfer through its states, from detection on the source
+/// chain up to confirmation on the target chain.
+///
+/// Each step is persisted in the store before the next one starts, so all
+/// pending transfers are resumed from their last state after a restart and
+/// any stage which failed is retried on the next tick.
+pub struct Orchestrator {
+    handler: Handler,
+    bsc: Arc<BscClient>,
+    stellar: Arc<Client>,
+    store: Arc<Store>,
+    bridge: Bridge,
+    metrics: BridgeMetrics,
+}
+
+impl Orchestrator {
+    pub fn new(
+        handler: Handler,
+        bsc: Arc<BscClient>,
+        stellar: Arc<Client>,
+        store: Arc<Store>,
+        bridge: Bridge,
+        metrics: BridgeMetrics,
+    ) -> Self {
+        Orchestrator {
+            handler,
+            bsc,
+            stellar,
+            store,
+            bridge,
+            metrics,
+        }
+    }
+
+    /// Process transfers until `shutdown` is set, the step in progress is finished first.
+    pub async fn run(self, mut shutdown: watch::Receiver<bool>) -> Result<(), Box<dyn Error>> {
+        let mut interval = time::interval(POLL_INTERVAL);
+        let mut events = self.handler.subscribe();
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                Ok(()) = shutdown.changed() => break,
+                Ok(event) = events.recv() => {
+                    if let HostEvent::PeerConnected { peer, .. } = event {
+                        debug!("{} connected, retrying transfers waiting for signatures", peer);
+                        self.retry_signing(&shutdown).await;
+                    }
+                    continue;
+                }
+            }
+
+            if let Err(e) = self.poll_deposits().await {
+                error!("failed to poll deposits: {}", e);
+                self.metrics
+                    .error(ErrorSource::of(e.as_ref(), ErrorSource::Horizon));
+            }
+            if let Err(e) = self.poll_withdraws().await {
+                error!("failed to poll withdraws: {}", e);
+                self.metrics
+                    .error(ErrorSource::of(e.as_ref(), ErrorSource::Bsc));
+            }
+
+            for transfer in self.pending_transfers() {
+                if *shutdown.borrow() {
+                    break;
+                }
+                if now() - transfer.updated_at > STUCK_AFTER {
+                    warn!(
+                        "{} {} is stuck in state {}, retrying",
+                        transfer.kind, transfer.id, transfer.state
+                    );
+                }
+                self.process(&transfer).await;
+            }
+        }
+
+        info!("orchestrator stopped");
+        Ok(())
+    }
+
+    // A signer came (back) online, the transfers it may sign don't have to wait for the next tick
+    async fn retry_signing(&self, shutdown: &watch::Receiver<bool>) {
+        for transfer in self.pending_transfers() {
+            if *shutdown.borrow() {
+                break;
+            }
+            if matches!(
+                transfer.state,
+                TransferState::Signing | TransferState::Refunding
+            ) {
+                self.process(&transfer).await;
+            }
+        }
+    }
+
+    // A failing store is retried on the next tick rather than stopping the bridge
+    fn pending_transfers(&self) -> Vec<Transfer> {
+        self.store.pending_transfers().unwrap_or_else(|e| {
+            error!("failed to load the pending transfers: {}", e);
+            self.metrics.error(ErrorSource::Store);
+            Vec::new()
+        })
+    }
+
+    async fn process(&self, transfer: &Transfer) {
+        let span = info_span!("transfer", kind = %transfer.kind, id = %transfer.id);
+        if let Err(e) = self.drive(&transfer.id).instrument(span).await {
+            error!("failed to process {} {}: {}", transfer.kind, transfer.id, e);
+            self.metrics
+                .error(ErrorSource::of(e.as_ref(), ErrorSource::Other));
+        }
+    }
+
+    async fn poll_deposits(&self) -> Result<(), Box<dyn Error>> {
+        // Without a checkpoint the bridge starts after the latest payment, old
+        // deposits were handled by the nodes which ran back then
+        let cursor = match self.store.horizon_cursor()? {
+            Some(cursor) => Some(cursor),
+            None => {
+                let latest = fetch_latest_payment_cursor(
+                    self.bridge.account.clone(),
+                    self.bridge.network.clone(),
+                )
+                .await?;
+                if let Some(cursor) = &latest {
+                    self.store.set_horizon_cursor(cursor)?;
+                }
+                latest
+            }
+        };
+
+        let payments = fetch_payments(
+            self.bridge.account.clone(),
+            cursor,
+            self.bridge.network.clone(),
+        )
+        .await?;
+
+        for payment in payments {
+            if classify(&self.bridge, &payment) != Deposit::Ignored {
+                info!("detected deposit {}", payment.transaction_hash);
+                self.store.insert_transfer(
+                    &payment.transaction_hash,
+                    TransferKind::Deposit,
+                    &bson::to_vec(&payment)?,
+                )?;
+            }
+            self.store.set_horizon_cursor(&payment.paging_token)?;
+        }
+
+        Ok(())
+    }
+
+    async fn poll_withdraws(&self) -> Result<(), Box<dyn Error>> {
+        let confirmed = self.bsc.confirmed_block().await?;
+        // Without a checkpoint the bridge starts from the current block
+        let from = match self.store.last_bsc_block()? {
+            Some(block) => block + 1,
+            None => confirmed,
+        };
+        if from > confirmed {
+            return Ok(());
+        }
+        let to = confirmed.min(from + MAX_BLOCK_RANGE - 1);
+
+        for withdraw in self.bsc.withdraws(from, to).await? {
+            let id = format!("{:?}", withdraw.tx_hash);
+            info!("detected withdraw {}", id);
+            self.store
+                .insert_transfer(&id, TransferKind::Withdraw, &bson::to_vec(&withdraw)?)?;
+        }
+        self.store.set_last_bsc_block(to)?;
+
+        Ok(())
+    }
+
+    /// Move a transfer forward until it reaches a final state or can't progress right now.
+    async fn drive(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        loop {
+            let transfer = match self.store.transfer(id)? {
+                Some(transfer) if !transfer.state.is_final() => transfer,
+                _ => return Ok(()),
+            };
+
+            let next = self.step(&transfer).await?;
+            // Steps which submit a transaction persist their state before doing so
+            let transfer = self
+                .store
+                .transfer(id)?
+                .ok_or_else(|| format!("transfer {} disappeared", id))?;
+            if next == transfer.state {
+                return Ok(());
+            }
+            self.transition(&transfer, next)?;
+        }
+    }
+
+    fn transition(&self, transfer: &Transfer, next: TransferState) -> Result<(), Box<dyn Error>> {
+        if !transfer.state.can_transition_to(next) {
+            return Err(format!(
+                "invalid transition of {} from {} to {}",
+                transfer.id, transfer.state, next
+            )
+            .into());
+        }
+
+        info!(
+            "{} {}: {} -> {}",
+            transfer.kind, transfer.id, transfer.state, next
+        );
+        self.store.set_transfer_state(&transfer.id, next)?;
+        if next.is_final() {
+            self.metrics.processed(transfer, next);
+        }
+        Ok(())
+    }
+
+    async fn step(&self, transfer: &Transfer) -> Result<TransferState, Box<dyn Error>> {
+        use TransferKind::*;
+        use TransferState::*;
+
+        match (transfer.kind, transfer.state) {
+            (Deposit, Detected) => self.validate_deposit(transfer),
+            (Withdraw, Detected) => self.validate_withdraw(transfer),
+            (Deposit, Validated) => {
+                let params: MintParams = bson::from_slice(&transfer.payload)?;
+                if self.bsc.is_minted(&params.tx_id).await? {
+                    return Ok(Confirmed);
+                }
+                Ok(Signing)
+            }
+            (Withdraw, Validated) => {
+                let mut payout: Payout = bson::from_slice(&transfer.payload)?;
+                if payout.is_paid(&self.bridge, &self.bsc).await? {
+                    return Ok(Confirmed);
+                }
+                // A fresh transaction invalidates the signatures over the previous one
+                payout.build(&self.bridge).await?;
+                self.store
+                    .set_transfer_payload(&transfer.id, &bson::to_vec(&payout)?)?;
+                self.store.clear_signatures(&transfer.id)?;
+                Ok(Signing)
+            }
+            (Deposit, Signing) => self.collect_mint_signatures(transfer).await,
+            (Withdraw, Signing) => {
+                let payout: Payout = bson::from_slice(&transfer.payload)?;
+                // The other signers refuse to sign a transaction which can't go through
+                if self.is_stale(&payout).await? {
+                    return Ok(Validated);
+                }
+                if self.collect_payout_signatures(transfer, &payout).await? {
+                    Ok(Signed)
+                } else {
+                    Ok(Signing)
+                }
+            }
+            (Deposit, Signed) => self.submit_mint(transfer).await,
+            (Withdraw, Signed) => {
+                let payout: Payout = bson::from_slice(&transfer.payload)?;
+                // Submitting the same transaction twice is harmless, the outcome
+                // is checked from the submitted state either way
+                self.submit_payout(transfer, &payout).await?;
+                Ok(Submitted)
+            }
+            (Deposit, Submitted) => {
+                let params: MintParams = bson::from_slice(&transfer.payload)?;
+                if self.bsc.is_minted(&params.tx_id).await? {
+                    Ok(Confirmed)
+                } else {
+                    Ok(Signed)
+                }
+            }
+            (Withdraw, Submitted) => {
+                let payout: Payout = bson::from_slice(&transfer.payload)?;
+                if payout.is_paid(&self.bridge, &self.bsc).await? {
+                    Ok(Confirmed)
+                } else if self.is_stale(&payout).await? {
+                    Ok(Validated)
+                } else {
+                    Ok(Signed)
+                }
+            }
+            (Deposit, Refunding) => self.refund(transfer).await,
+            (_, state) => Ok(state),
+        }
+    }
+
+    fn validate_deposit(&self, transfer: &Transfer) -> Result<TransferState, Box<dyn Error>> {
+        let payment: Payment = bson::from_slice(&transfer.payload)?;
+
+        match classify(&self.bridge, &payment) {
+            Deposit::Valid(params) => {
+                self.store
+                    .set_transfer_payload(&transfer.id, &bson::to_vec(&params)?)?;
+                Ok(TransferState::Validated)
+            }
+            Deposit::Invalid(params) => {
+                warn!(
+                    "deposit {} has no valid receiver, refunding {}",
+                    transfer.id, params.receiver
+                );
+                self.store
+                    .set_transfer_payload(&transfer.id, &bson::to_vec(&Payout::Refund(params))?)?;
+                Ok(TransferState::Refunding)
+            }
+            Deposit::Ignored => Ok(TransferState::Failed),
+        }
+    }
+
+    fn validate_withdraw(&self, transfer: &Transfer) -> Result<TransferState, Box<dyn Error>> {
+        let withdraw: Withdraw = bson::from_slice(&transfer.payload)?;
+
+        match WithdrawParams::from_event(&withdraw) {
+            Some(params) => {
+                self.store.set_transfer_payload(
+                    &transfer.id,
+                    &bson::to_vec(&Payout::Withdraw(params))?,
+                )?;
+                Ok(TransferState::Validated)
+            }
+            None => {
+                warn!(
+                    "withdraw {} to {} on {} can't be paid out",
+                    transfer.id, withdraw.receiver, withdraw.network
+                );
+                Ok(TransferState::Failed)
+            }
+        }
+    }
+
+    async fn collect_mint_signatures(
+        &self,
+        transfer: &Transfer,
+    ) -> Result<TransferState, Box<dyn Error>> {
+        let params: MintParams = bson::from_slice(&transfer.payload)?;
+        if self.bsc.is_minted(&params.tx_id).await? {
+            return Ok(TransferState::Confirmed);
+        }
+
+        let signature = self.bsc.sign_mint(&params).await?;
+        self.store.add_signature(&transfer.id, &signature)?;
+
+        let signers = self.bsc.signers().await?;
+        let collected = self
+            .collect_signatures(
+                transfer,
+                SignRequest::MintRequest(bson::to_vec(&params)?),
+                |signature| mint_signer(&params, signature, &signers),
+            )
+            .await?;
+
+        if collected {
+            Ok(TransferState::Signed)
+        } else {
+            Ok(TransferState::Signing)
+        }
+    }
+
+    async fn collect_payout_signatures(
+        &self,
+        transfer: &Transfer,
+        payout: &Payout,
+    ) -> Result<bool, Box<dyn Error>> {
+        let tx = payout
+            .transaction()?
+            .ok_or_else(|| format!("payout {} has no transaction", transfer.id))?;
+
+        let signature = self.stellar.sign_transaction(&tx).await?;
+        self.store
+            .add_signature(&transfer.id, &encode_signature(&signature))?;
+
+        let keys = self.signer_keys().await?;
+        self.collect_signatures(
+            transfer,
+            SignRequest::StellarRequest(bson::to_vec(payout)?),
+            |signature| self.payout_signer(&tx, signature, &keys),
+        )
+        .await
+    }
+
+    // Signed with the Stellar key of this node, so the signers know who asks
+    async fn sign_request(&self, request: SignRequest) -> Result<SignedRequest, Box<dyn Error>> {
+        let payload = request.signing_payload(&self.handler.local_peer_id())?;
+        let signature = RequestSignature {
+            public_key: self.stellar.public_key().account_id(),
+            signature: self.stellar.sign_message(&payload).await?,
+        };
+        Ok(SignedRequest {
+            request,
+            signature: Some(signature),
+        })
+    }
+
+    /// Ask the other signers for the signatures still missing, returns whether
+    /// enough of them are stored now. Only the signatures `signer_of` recovers a
+    /// signer from are kept, and each signer is counted once.
+    async fn collect_signatures<K: Eq + Hash>(
+        &self,
+        transfer: &Transfer,
+        request: SignRequest,
+        signer_of: impl Fn(&[u8]) -> Option<K>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let stored = distinct_signatures(self.store.signatures(&transfer.id)?, &signer_of).len();
+        if stored >= self.bridge.min_signatures {
+            return Ok(true);
+        }
+
+        let request = self.sign_request(request).await?;
+        let responses = match self
+            .handler
+            .send_signed(request, self.bridge.min_signatures - stored)
+            .await
+        {
+            Ok(responses) => responses,
+            Err(e) => {
+                warn!("failed to collect signatures for {}: {}", transfer.id, e);
+                return Ok(false);
+            }
+        };
+
+        for response in responses {
+            if signer_of(&response).is_some() {
+                self.store.add_signature(&transfer.id, &response)?;
+            } else {
+                debug!("dropping invalid signature for {}", transfer.id);
+            }
+        }
+
+        let stored = distinct_signatures(self.store.signatures(&transfer.id)?, &signer_of).len();
+        debug!(
+            "collected {}/{} signatures for {}",
+            stored, self.bridge.min_signatures, transfer.id
+        );
+        Ok(stored >= self.bridge.min_signatures)
+    }
+
+    async fn submit_mint(&self, transfer: &Transfer) -> Result<TransferState, Box<dyn Error>> {
+        let params: MintParams = bson::from_slice(&transfer.payload)?;
+        let signers = self.bsc.signers().await?;
+        let signatures: Vec<_> = distinct_signatures(self.store.signatures(&transfer.id)?, |s| {
+            mint_signer(&params, s, &signers)
+        })
+        .into_iter()
+        .take(self.bridge.min_signatures)
+        .collect();
+
+        self.transition(transfer, TransferState::Submitted)?;
+        let next = match self.bsc.submit_mint(&params, &signatures).await? {
+            MintOutcome::Confirmed { .. } | MintOutcome::AlreadyMinted => TransferState::Confirmed,
+            // Another signer may have minted it first
+            MintOutcome::Reverted { .. } if self.bsc.is_minted(&params.tx_id).await? => {
+                TransferState::Confirmed
+            }
+            // The deposit still has to be minted, collect the signatures again in
+            // case the signers of the contract changed
+            MintOutcome::Reverted { reason, .. } => {
+                error!("mint {} reverted: {:?}", transfer.id, reason);
+                self.store.clear_signatures(&transfer.id)?;
+                TransferState::Validated
+            }
+            MintOutcome::Replaced { .. } => TransferState::Signed,
+        };
+        Ok(next)
+    }
+
+    /// Attach the collected signatures to the payout transaction and submit it,
+    /// returns whether it was accepted.
+    async fn submit_payout(
+        &self,
+        transfer: &Transfer,
+        payout: &Payout,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mut tx = payout
+            .transaction()?
+            .ok_or_else(|| format!("payout {} has no transaction", transfer.id))?;
+
+        let keys = self.signer_keys().await?;
+        let signatures = distinct_signatures(self.store.signatures(&transfer.id)?, |s| {
+            self.payout_signer(&tx, s, &keys)
+        });
+        // Stellar rejects transactions carrying more signatures than needed
+        let signatures = signatures
+            .into_iter()
+            .take(self.bridge.min_signatures)
+            .map(|signature| decode_signature(&signature))
+            .collect::<Result<Vec<_>, _>>()?;
+        tx.signatures_mut().extend(signatures);
+
+        match submit_transaction(&tx, self.bridge.network.clone()).await {
+            Ok(hash) => {
+                info!("payout {} submitted in tx {}", transfer.id, hash);
+                Ok(true)
+            }
+            Err(e) => {
+                warn!("failed to submit payout {}: {}", transfer.id, e);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Whether the payout transaction expired or its sequence number was used up, in
+    /// which case it can never be included anymore and a new one has to be built.
+    async fn is_stale(&self, payout: &Payout) -> Result<bool, Box<dyn Error>> {
+        let tx = match payout.transaction()? {
+            Some(tx) => tx,
+            None => return Ok(true),
+        };
+        if is_expired(&tx) {
+            return Ok(true);
+        }
+        let account = PublicKey::from_account_id(&self.bridge.account)?;
+        let sequence = fetch_sequence(&account, self.bridge.network.clone()).await?;
+
+        Ok(sequence >= *tx.sequence())
+    }
+
+    /// Refunds are built, signed and submitted from the refunding state, there is
+    /// nothing to mint so the regular signing states are skipped.
+    async fn refund(&self, transfer: &Transfer) -> Result<TransferState, Box<dyn Error>> {
+        let mut payout: Payout = bson::from_slice(&transfer.payload)?;
+        if payout.is_paid(&self.bridge, &self.bsc).await? {
+            return Ok(TransferState::Refunded);
+        }
+
+        if self.is_stale(&payout).await? {
+            payout.build(&self.bridge).await?;
+            self.store
+                .set_transfer_payload(&transfer.id, &bson::to_vec(&payout)?)?;
+            self.store.clear_signatures(&transfer.id)?;
+        }
+
+        if !self.collect_payout_signatures(transfer, &payout).await? {
+            return Ok(TransferState::Refunding);
+        }
+
+        if self.submit_payout(transfer, &payout).await? {
+            Ok(TransferState::Refunded)
+        } else {
+            Ok(TransferState::Refunding)
+        }
+    }
+
+    /// Keys of the Stellar signers of the bridge account.
+    async fn signer_keys(&self) -> Result<Vec<PublicKey>, Box<dyn Error>> {
+        let signers = self.bridge.signers().await?;
+
+        Ok(signers
+            .iter()
+            .filter_map(|s| PublicKey::from_account_id(&s.key).ok())
+            .collect())
+    }
+
+    /// Signer out of `keys` which produced `signature` over `tx`, if any.
+    fn payout_signer(
+        &self,
+        tx: &Transaction,
+        signature: &[u8],
+        keys: &[PublicKey],
+    ) -> Option<String> {
+        let signature = decode_signature(signature).ok()?;
+        verify_signature(tx, &signature, keys, self.bridge.network.clone())
+            .ok()
+            .flatten()
+            .map(|key| key.account_id())
+    }
+}
+
+/// Contract signer out of `signers` which produced `signature` over the mint, if any.
+fn mint_signer(params: &MintParams, signature: &[u8], signers: &[Address]) -> Option<Address> {
+    params
+        .recover_signer(signature)
+        .filter(|signer| signers.contains(signer))
+}
+
+/// The signatures of distinct signers out of `signatures`, dropping the ones
+/// `signer_of` doesn't recover a signer from.
+fn distinct_signatures<K: Eq + Hash>(
+    signatures: Vec<Vec<u8>>,
+    signer_of: impl Fn(&[u8]) -> Option<K>,
+) -> Vec<Vec<u8>> {
+    let mut seen = HashSet::new();
+    signatures
+        .into_iter()
+        .filter(|signature| signer_of(signature).is_some_and(|signer| seen.insert(signer)))
+        .collect()
+}
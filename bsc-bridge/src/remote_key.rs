@@ -0,0 +1,290 @@
+use async_trait::async_trait;
+use hyper::{
+    body,
+    client::conn,
+    header::{CONTENT_TYPE, HOST},
+    Body, Method, Request, Response, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, str::FromStr, time::Duration};
+use tf_bsc::{
+    keys::{KeyBackend as BscKeyBackend, KeyError as BscKeyError},
+    types::{Address, Signature as BscSignature, H256},
+};
+use tf_stellar::{
+    keys::{KeyBackend as StellarKeyBackend, KeyError as StellarKeyError, Signature},
+    PublicKey,
+};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpStream, UnixStream},
+    time,
+};
+use tracing::debug;
+use url::Url;
+
+// Keys held by a separate signing service, so the secrets never enter the bridge process.
+// The service exposes every key under a name:
+//   GET  /keys/<name>       -> {"address": "<Stellar or BSC address of the key>"}
+//   POST /keys/<name>/sign  {"hash": "<hex>"} -> {"signature": "<hex>"}
+// Signatures are 64 bytes for ed25519 keys and 65 bytes `r || s || v` for secp256k1 keys.
+
+const UNIX_SCHEME: &str = "unix:";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Error, Debug)]
+pub enum RemoteKeyError {
+    #[error("invalid signer url {0}, expected http://host:port or unix:/path")]
+    InvalidUrl(String),
+    #[error("failed to reach signer: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("http error: {0}")]
+    Http(#[from] hyper::Error),
+    #[error("signer answered {0}: {1}")]
+    Status(StatusCode, String),
+    #[error("invalid response from signer: {0}")]
+    InvalidResponse(String),
+    #[error("signer did not answer in time")]
+    Timeout,
+}
+
+#[derive(Clone)]
+enum Endpoint {
+    Http(Url),
+    Unix(PathBuf),
+}
+
+/// Client of a signing service reachable over HTTP or a Unix socket.
+#[derive(Clone)]
+pub struct RemoteSigner {
+    endpoint: Endpoint,
+}
+
+#[derive(Deserialize)]
+struct KeyResponse {
+    address: String,
+}
+
+#[derive(Serialize)]
+struct SignRequest {
+    hash: String,
+}
+
+#[derive(Deserialize)]
+struct SignResponse {
+    signature: String,
+}
+
+impl RemoteSigner {
+    pub fn new(url: &str) -> Result<Self, RemoteKeyError> {
+        let endpoint = match url.strip_prefix(UNIX_SCHEME) {
+            Some(path) if !path.is_empty() => Endpoint::Unix(PathBuf::from(path)),
+            Some(_) => return Err(RemoteKeyError::InvalidUrl(url.to_string())),
+            None => match Url::parse(url) {
+                Ok(parsed) if parsed.scheme() == "http" && parsed.host_str().is_some() => {
+                    Endpoint::Http(parsed)
+                }
+                _ => return Err(RemoteKeyError::InvalidUrl(url.to_string())),
+            },
+        };
+        Ok(RemoteSigner { endpoint })
+    }
+
+    /// The Stellar key `name` of the signer.
+    pub async fn stellar_key(&self, name: &str) -> Result<RemoteStellarKey, RemoteKeyError> {
+        let address = self.address(name).await?;
+        let public_key = PublicKey::from_account_id(&address)
+            .map_err(|_| RemoteKeyError::InvalidResponse(format!("invalid address {}", address)))?;
+
+        Ok(RemoteStellarKey {
+            signer: self.clone(),
+            name: name.to_string(),
+            public_key,
+        })
+    }
+
+    /// The BSC key `name` of the signer.
+    pub async fn bsc_key(&self, name: &str) -> Result<RemoteBscKey, RemoteKeyError> {
+        let address = self.address(name).await?;
+        let address = Address::from_str(&address)
+            .map_err(|_| RemoteKeyError::InvalidResponse(format!("invalid address {}", address)))?;
+
+        Ok(RemoteBscKey {
+            signer: self.clone(),
+            name: name.to_string(),
+            address,
+        })
+    }
+
+    async fn address(&self, name: &str) -> Result<String, RemoteKeyError> {
+        let response: KeyResponse = self
+            .request(Method::GET, &format!("keys/{}", name), Body::empty())
+            .await?;
+        Ok(response.address)
+    }
+
+    async fn sign(&self, name: &str, hash: &[u8]) -> Result<Vec<u8>, RemoteKeyError> {
+        let request = SignRequest {
+            hash: hex::encode(hash),
+        };
+        let body = serde_json::to_vec(&request)
+            .map_err(|e| RemoteKeyError::InvalidResponse(e.to_string()))?;
+
+        let response: SignResponse = self
+            .request(
+                Method::POST,
+                &format!("keys/{}/sign", name),
+                Body::from(body),
+            )
+            .await?;
+        hex::decode(response.signature.trim_start_matches("0x"))
+            .map_err(|_| RemoteKeyError::InvalidResponse("signature is not hex".to_string()))
+    }
+
+    async fn request<T: for<'de> Deserialize<'de>>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Body,
+    ) -> Result<T, RemoteKeyError> {
+        let (uri, host) = match &self.endpoint {
+            Endpoint::Http(url) => {
+                let base = url.path().trim_end_matches('/');
+                let host = match url.port() {
+                    Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+                    None => url.host_str().unwrap_or_default().to_string(),
+                };
+                (format!("{}/{}", base, path), host)
+            }
+            Endpoint::Unix(_) => (format!("/{}", path), "localhost".to_string()),
+        };
+
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(HOST, host)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .map_err(|e| RemoteKeyError::InvalidResponse(e.to_string()))?;
+
+        let response = time::timeout(REQUEST_TIMEOUT, self.send(request))
+            .await
+            .map_err(|_| RemoteKeyError::Timeout)??;
+
+        let status = response.status();
+        let bytes = body::to_bytes(response.into_body()).await?;
+        if !status.is_success() {
+            return Err(RemoteKeyError::Status(
+                status,
+                String::from_utf8_lossy(&bytes).to_string(),
+            ));
+        }
+
+        serde_json::from_slice(&bytes).map_err(|e| RemoteKeyError::InvalidResponse(e.to_string()))
+    }
+
+    async fn send(&self, request: Request<Body>) -> Result<Response<Body>, RemoteKeyError> {
+        match &self.endpoint {
+            Endpoint::Http(url) => {
+                let host = url.host_str().unwrap_or_default();
+                let port = url.port_or_known_default().unwrap_or(80);
+                send_over(TcpStream::connect((host, port)).await?, request).await
+            }
+            Endpoint::Unix(path) => send_over(UnixStream::connect(path).await?, request).await,
+        }
+    }
+}
+
+// The signer is only called now and then, a connection per request keeps it simple
+async fn send_over<S>(stream: S, request: Request<Body>) -> Result<Response<Body>, RemoteKeyError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = conn::handshake(stream).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            debug!("signer connection closed: {}", e);
+        }
+    });
+
+    Ok(sender.send_request(request).await?)
+}
+
+/// Stellar key held by a signing service.
+pub struct RemoteStellarKey {
+    signer: RemoteSigner,
+    name: String,
+    public_key: PublicKey,
+}
+
+#[async_trait]
+impl StellarKeyBackend for RemoteStellarKey {
+    fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    async fn sign_hash(&self, hash: &[u8]) -> Result<Signature, StellarKeyError> {
+        let signature = self
+            .signer
+            .sign(&self.name, hash)
+            .await
+            .map_err(|e| StellarKeyError::Backend(e.to_string()))?;
+        let signature = Signature::from_slice(&signature)
+            .map_err(|e| StellarKeyError::Backend(e.to_string()))?;
+
+        // Don't pass on a signature of another key, the transaction would just fail later on
+        if !signature.verify(&self.public_key, hash) {
+            return Err(StellarKeyError::Backend(format!(
+                "signature of {} does not match its public key",
+                self.name
+            )));
+        }
+        Ok(signature)
+    }
+}
+
+/// BSC key held by a signing service.
+pub struct RemoteBscKey {
+    signer: RemoteSigner,
+    name: String,
+    address: Address,
+}
+
+#[async_trait]
+impl BscKeyBackend for RemoteBscKey {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_hash(&self, hash: H256) -> Result<BscSignature, BscKeyError> {
+        let signature = self
+            .signer
+            .sign(&self.name, hash.as_bytes())
+            .await
+            .map_err(|e| BscKeyError::Backend(e.to_string()))?;
+        let mut signature = BscSignature::try_from(signature.as_slice())
+            .map_err(|e| BscKeyError::Backend(e.to_string()))?;
+
+        // Services return the recovery id either as is or plus 27, the backend must do the latter
+        signature.v = match signature.v {
+            0 | 1 => signature.v + 27,
+            27 | 28 => signature.v,
+            v => {
+                return Err(BscKeyError::Backend(format!(
+                    "signature of {} has an invalid v {}",
+                    self.name, v
+                )))
+            }
+        };
+
+        // Don't pass on a signature of another key, the contract would just reject it later on
+        if signature.verify(hash, self.address).is_err() {
+            return Err(BscKeyError::Backend(format!(
+                "signature of {} does not match its address",
+                self.name
+            )));
+        }
+        Ok(signature)
+    }
+}
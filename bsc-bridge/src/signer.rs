@@ -0,0 +1,171 @@
+use crate::{
+    bridge::Bridge,
+    mint,
+    withdraw::{self, Payout},
+};
+use async_trait::async_trait;
+use std::{error::Error, sync::Arc};
+use tf_bsc::{mint::MintParams, Client as BscClient};
+use tf_libp2p::{
+    traits::{RequestOrigin, Signer, SignerError},
+    types::{SignRequest, SignResponse},
+};
+use tf_stellar::{transaction::encode_signature, verify_message, Client, PublicKey};
+use tracing::{error, info, instrument, warn};
+
+/// Signs the requests of the other signers, after checking them against both chains.
+pub struct TxSigner {
+    bsc: Arc<BscClient>,
+    stellar: Arc<Client>,
+    bridge: Bridge,
+}
+
+impl TxSigner {
+    pub fn new(bsc: Arc<BscClient>, stellar: Arc<Client>, bridge: Bridge) -> Self {
+        TxSigner {
+            bsc,
+            stellar,
+            bridge,
+        }
+    }
+
+    // A signed request must come from a signer of the bridge account, an unsigned one
+    // is only taken while the bridge doesn't require signatures
+    async fn check_origin(
+        &self,
+        origin: &RequestOrigin,
+        request: &SignRequest,
+    ) -> Result<(), SignerError> {
+        let signature = match &origin.signature {
+            Some(signature) => signature,
+            None if self.bridge.require_signed_requests => {
+                warn!(peer = %origin.peer, "refusing unsigned request");
+                return Err(SignerError::Unauthorized);
+            }
+            None => return Ok(()),
+        };
+
+        let key = PublicKey::from_account_id(&signature.public_key)
+            .map_err(|_| SignerError::InvalidRequest)?;
+        let payload = request
+            .signing_payload(&origin.peer)
+            .map_err(|_| SignerError::InvalidRequest)?;
+        if !verify_message(&key, &payload, &signature.signature) {
+            warn!(peer = %origin.peer, "refusing request with an invalid signature of {}", signature.public_key);
+            return Err(SignerError::Unauthorized);
+        }
+
+        let signers = self.bridge.signers().await.map_err(|e| {
+            error!("failed to fetch the signers of the bridge account: {}", e);
+            SignerError::FailedToSign
+        })?;
+        if !signers.iter().any(|s| s.key == signature.public_key) {
+            warn!(peer = %origin.peer, "refusing request of {}, not a signer of the bridge account", signature.public_key);
+            return Err(SignerError::Unauthorized);
+        }
+        Ok(())
+    }
+
+    #[instrument(name = "transfer", skip_all, fields(kind = "deposit", id = %params.tx_id))]
+    async fn sign_mint(&self, params: &MintParams) -> Result<SignResponse, SignerError> {
+        if !mint::verify(&self.bridge, params)
+            .await
+            .map_err(|e| failed(&params.tx_id, e))?
+        {
+            warn!("refusing to sign mint {}, no such deposit", params.tx_id);
+            return Err(SignerError::InvalidRequest);
+        }
+
+        let minted = self
+            .bsc
+            .is_minted(&params.tx_id)
+            .await
+            .map_err(|e| failed(&params.tx_id, e.into()))?;
+        if minted {
+            info!("refusing to sign mint {}, already minted", params.tx_id);
+            return Err(SignerError::AlreadyProcessed);
+        }
+
+        self.bsc.sign_mint(params).await.map_err(|e| {
+            error!("failed to sign mint {}: {}", params.tx_id, e);
+            SignerError::FailedToSign
+        })
+    }
+
+    #[instrument(name = "transfer", skip_all, fields(id = %payout.source_tx()))]
+    async fn sign_payout(&self, payout: &Payout) -> Result<SignResponse, SignerError> {
+        let id = payout.source_tx();
+
+        let valid = match payout {
+            Payout::Withdraw(params) => withdraw::verify_withdraw(&self.bsc, params).await,
+            Payout::Refund(params) => withdraw::verify_refund(&self.bridge, params).await,
+        }
+        .map_err(|e| failed(id, e))?;
+        if !valid {
+            warn!("refusing to sign payout {}, no such source transaction", id);
+            return Err(SignerError::InvalidRequest);
+        }
+
+        let tx = match payout.transaction() {
+            Ok(Some(tx)) if payout.matches(&tx, &self.bridge).unwrap_or(false) => tx,
+            _ => {
+                warn!("refusing to sign payout {}, unexpected transaction", id);
+                return Err(SignerError::InvalidRequest);
+            }
+        };
+        if !withdraw::is_next_payout(&tx, &self.bridge)
+            .await
+            .map_err(|e| failed(id, e))?
+        {
+            warn!(
+                "refusing to sign payout {}, wrong fee, sequence or time bounds",
+                id
+            );
+            return Err(SignerError::InvalidRequest);
+        }
+
+        if payout
+            .is_paid(&self.bridge, &self.bsc)
+            .await
+            .map_err(|e| failed(id, e))?
+        {
+            info!("refusing to sign payout {}, already paid out", id);
+            return Err(SignerError::AlreadyProcessed);
+        }
+
+        let signature = self.stellar.sign_transaction(&tx).await.map_err(|e| {
+            error!("failed to sign payout {}: {}", id, e);
+            SignerError::FailedToSign
+        })?;
+        Ok(encode_signature(&signature))
+    }
+}
+
+#[async_trait]
+impl Signer for TxSigner {
+    async fn sign(
+        &self,
+        origin: &RequestOrigin,
+        message: &SignRequest,
+    ) -> Result<SignResponse, SignerError> {
+        self.check_origin(origin, message).await?;
+
+        match message {
+            SignRequest::MintRequest(payload) => {
+                let params: MintParams =
+                    bson::from_slice(payload).map_err(|_| SignerError::InvalidRequest)?;
+                self.sign_mint(&params).await
+            }
+            SignRequest::StellarRequest(payload) => {
+                let payout: Payout =
+                    bson::from_slice(payload).map_err(|_| SignerError::InvalidRequest)?;
+                self.sign_payout(&payout).await
+            }
+        }
+    }
+}
+
+fn failed(id: &str, e: Box<dyn Error>) -> SignerError {
+    error!("failed to check transfer {}: {}", id, e);
+    SignerError::FailedToSign
+}
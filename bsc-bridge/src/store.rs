@@ -0,0 +1,375 @@
+use crate::transfer::{TransferKind, TransferState};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::{
+    fs,
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+const DB_FILE: &str = "bridge.db";
+
+const HORIZON_CURSOR: &str = "horizon_cursor";
+const LAST_BSC_BLOCK: &str = "last_bsc_block";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS checkpoints (
+        name TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS transfers (
+        id TEXT PRIMARY KEY,
+        kind TEXT NOT NULL,
+        state TEXT NOT NULL,
+        payload BLOB NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS signatures (
+        transfer_id TEXT NOT NULL REFERENCES transfers(id),
+        signature BLOB NOT NULL,
+        PRIMARY KEY (transfer_id, signature)
+    );
+";
+
+#[derive(Error, Debug)]
+pub enum StoreError {
+    #[error("failed to create data dir: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("invalid value stored for {0}")]
+    Corrupted(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    /// Hash of the source transaction
+    pub id: String,
+    pub kind: TransferKind,
+    pub state: TransferState,
+    /// Bson encoded parameters which are signed
+    pub payload: Vec<u8>,
+    /// Unix timestamp of the last state change
+    pub updated_at: i64,
+}
+
+/// Bridge state persisted in the data dir so a restarted node resumes where it stopped.
+pub struct Store {
+    conn: Mutex<Connection>,
+}
+
+impl Store {
+    pub fn open(data_dir: &Path) -> Result<Self, StoreError> {
+        fs::create_dir_all(data_dir)?;
+
+        let conn = Connection::open(data_dir.join(DB_FILE))?;
+        // WAL allows reading the store while the node is writing to it
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::with_connection(conn)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self, StoreError> {
+        conn.execute_batch(SCHEMA)?;
+
+        Ok(Store {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Paging token of the last processed Horizon payment.
+    pub fn horizon_cursor(&self) -> Result<Option<String>, StoreError> {
+        self.checkpoint(HORIZON_CURSOR)
+    }
+
+    pub fn set_horizon_cursor(&self, cursor: &str) -> Result<(), StoreError> {
+        self.set_checkpoint(HORIZON_CURSOR, cursor)
+    }
+
+    /// Last BSC block that was fully processed.
+    pub fn last_bsc_block(&self) -> Result<Option<u64>, StoreError> {
+        self.checkpoint(LAST_BSC_BLOCK)?
+            .map(|b| {
+                b.parse()
+                    .map_err(|_| StoreError::Corrupted(LAST_BSC_BLOCK.to_string()))
+            })
+            .transpose()
+    }
+
+    pub fn set_last_bsc_block(&self, block: u64) -> Result<(), StoreError> {
+        self.set_checkpoint(LAST_BSC_BLOCK, &block.to_string())
+    }
+
+    pub fn transfer(&self, id: &str) -> Result<Option<Transfer>, StoreError> {
+        let conn = self.conn.lock().expect("store lock poisoned");
+        conn.query_row(
+            "SELECT id, kind, state, payload, updated_at FROM transfers WHERE id = ?1",
+            params![id],
+            read_transfer,
+        )
+        .optional()?
+        .map(to_transfer)
+        .transpose()
+    }
+
+    /// Transfers which did not reach a final state yet, oldest first.
+    pub fn pending_transfers(&self) -> Result<Vec<Transfer>, StoreError> {
+        let conn = self.conn.lock().expect("store lock poisoned");
+        let mut stmt = conn.prepare(
+            "SELECT id, kind, state, payload, updated_at FROM transfers
+             WHERE state NOT IN (?1, ?2, ?3) ORDER BY updated_at",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                TransferState::Confirmed.to_string(),
+                TransferState::Refunded.to_string(),
+                TransferState::Failed.to_string()
+            ],
+            read_transfer,
+        )?;
+
+        rows.map(|row| to_transfer(row?)).collect()
+    }
+
+    /// Number of transfers in every state.
+    pub fn transfer_counts(&self) -> Result<Vec<(TransferState, u64)>, StoreError> {
+        let conn = self.conn.lock().expect("store lock poisoned");
+        let mut stmt =
+            conn.prepare("SELECT state, COUNT(*) FROM transfers GROUP BY state ORDER BY state")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?;
+
+        rows.map(|row| {
+            let (state, count) = row?;
+            let state = state
+                .parse()
+                .map_err(|_| StoreError::Corrupted(format!("state {}", state)))?;
+            Ok((state, count as u64))
+        })
+        .collect()
+    }
+
+    /// Insert a new transfer, or return the one already stored under the same id.
+    pub fn insert_transfer(
+        &self,
+        id: &str,
+        kind: TransferKind,
+        payload: &[u8],
+    ) -> Result<Transfer, StoreError> {
+        {
+            let conn = self.conn.lock().expect("store lock poisoned");
+            conn.execute(
+                "INSERT OR IGNORE INTO transfers (id, kind, state, payload, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    id,
+                    kind.to_string(),
+                    TransferState::Detected.to_string(),
+                    payload,
+                    now()
+                ],
+            )?;
+        }
+
+        self.transfer(id)?
+            .ok_or_else(|| StoreError::Corrupted(id.to_string()))
+    }
+
+    pub fn set_transfer_state(&self, id: &str, state: TransferState) -> Result<(), StoreError> {
+        let conn = self.conn.lock().expect("store lock poisoned");
+        conn.execute(
+            "UPDATE transfers SET state = ?2, updated_at = ?3 WHERE id = ?1",
+            params![id, state.to_string(), now()],
+        )?;
+        Ok(())
+    }
+
+    /// Replace the parameters of a transfer, for instance once it is validated.
+    pub fn set_transfer_payload(&self, id: &str, payload: &[u8]) -> Result<(), StoreError> {
+        let conn = self.conn.lock().expect("store lock poisoned");
+        conn.execute(
+            "UPDATE transfers SET payload = ?2 WHERE id = ?1",
+            params![id, payload],
+        )?;
+        Ok(())
+    }
+
+    /// Record a signature collected for a transfer, duplicates are ignored.
+    pub fn add_signature(&self, transfer_id: &str, signature: &[u8]) -> Result<(), StoreError> {
+        let conn = self.conn.lock().expect("store lock poisoned");
+        conn.execute(
+            "INSERT OR IGNORE INTO signatures (transfer_id, signature) VALUES (?1, ?2)",
+            params![transfer_id, signature],
+        )?;
+        Ok(())
+    }
+
+    pub fn signatures(&self, transfer_id: &str) -> Result<Vec<Vec<u8>>, StoreError> {
+        let conn = self.conn.lock().expect("store lock poisoned");
+        let mut stmt = conn.prepare("SELECT signature FROM signatures WHERE transfer_id = ?1")?;
+        let rows = stmt.query_map(params![transfer_id], |row| row.get(0))?;
+
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Drop the signatures of a transfer when the signed parameters changed.
+    pub fn clear_signatures(&self, transfer_id: &str) -> Result<(), StoreError> {
+        let conn = self.conn.lock().expect("store lock poisoned");
+        conn.execute(
+            "DELETE FROM signatures WHERE transfer_id = ?1",
+            params![transfer_id],
+        )?;
+        Ok(())
+    }
+
+    fn checkpoint(&self, name: &str) -> Result<Option<String>, StoreError> {
+        let conn = self.conn.lock().expect("store lock poisoned");
+        Ok(conn
+            .query_row(
+                "SELECT value FROM checkpoints WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn set_checkpoint(&self, name: &str, value: &str) -> Result<(), StoreError> {
+        let conn = self.conn.lock().expect("store lock poisoned");
+        conn.execute(
+            "INSERT INTO checkpoints (name, value) VALUES (?1, ?2)
+             ON CONFLICT(name) DO UPDATE SET value = excluded.value",
+            params![name, value],
+        )?;
+        Ok(())
+    }
+}
+
+type TransferRow = (String, String, String, Vec<u8>, i64);
+
+fn read_transfer(row: &Row) -> rusqlite::Result<TransferRow> {
+    Ok((
+        row.get(0)?,
+        row.get(1)?,
+        row.get(2)?,
+        row.get(3)?,
+        row.get(4)?,
+    ))
+}
+
+fn to_transfer(row: TransferRow) -> Result<Transfer, StoreError> {
+    let (id, kind, state, payload, updated_at) = row;
+    Ok(Transfer {
+        kind: kind
+            .parse()
+            .map_err(|_| StoreError::Corrupted(format!("kind of {}", id)))?,
+        state: state
+            .parse()
+            .map_err(|_| StoreError::Corrupted(format!("state of {}", id)))?,
+        id,
+        payload,
+        updated_at,
+    })
+}
+
+/// Current Unix time in seconds, as stored in `updated_at`.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> Store {
+        Store::with_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn checkpoints_round_trip() {
+        let store = store();
+        assert_eq!(store.horizon_cursor().unwrap(), None);
+        assert_eq!(store.last_bsc_block().unwrap(), None);
+
+        store.set_horizon_cursor("123-1").unwrap();
+        store.set_horizon_cursor("456-2").unwrap();
+        store.set_last_bsc_block(42).unwrap();
+
+        assert_eq!(store.horizon_cursor().unwrap().as_deref(), Some("456-2"));
+        assert_eq!(store.last_bsc_block().unwrap(), Some(42));
+    }
+
+    #[test]
+    fn transfers_round_trip() {
+        let store = store();
+        assert!(store.transfer("tx").unwrap().is_none());
+
+        let inserted = store
+            .insert_transfer("tx", TransferKind::Deposit, b"payload")
+            .unwrap();
+        assert_eq!(inserted.kind, TransferKind::Deposit);
+        assert_eq!(inserted.state, TransferState::Detected);
+        assert_eq!(inserted.payload, b"payload");
+
+        // A transfer seen again keeps its progress
+        store
+            .set_transfer_state("tx", TransferState::Validated)
+            .unwrap();
+        let again = store
+            .insert_transfer("tx", TransferKind::Deposit, b"other")
+            .unwrap();
+        assert_eq!(again.state, TransferState::Validated);
+        assert_eq!(again.payload, b"payload");
+
+        store.set_transfer_payload("tx", b"validated").unwrap();
+        let transfer = store.transfer("tx").unwrap().unwrap();
+        assert_eq!(transfer.state, TransferState::Validated);
+        assert_eq!(transfer.payload, b"validated");
+    }
+
+    #[test]
+    fn pending_transfers_skip_final_states() {
+        let store = store();
+        store
+            .insert_transfer("pending", TransferKind::Withdraw, b"")
+            .unwrap();
+        store
+            .insert_transfer("done", TransferKind::Deposit, b"")
+            .unwrap();
+        store
+            .set_transfer_state("done", TransferState::Confirmed)
+            .unwrap();
+
+        let pending = store.pending_transfers().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, "pending");
+        assert_eq!(pending[0].kind, TransferKind::Withdraw);
+    }
+
+    #[test]
+    fn signatures_are_added_once_and_cleared() {
+        let store = store();
+        store
+            .insert_transfer("tx", TransferKind::Deposit, b"")
+            .unwrap();
+        store
+            .insert_transfer("other", TransferKind::Deposit, b"")
+            .unwrap();
+
+        store.add_signature("tx", b"a").unwrap();
+        store.add_signature("tx", b"b").unwrap();
+        store.add_signature("tx", b"a").unwrap();
+        store.add_signature("other", b"c").unwrap();
+
+        let mut signatures = store.signatures("tx").unwrap();
+        signatures.sort();
+        assert_eq!(signatures, vec![b"a".to_vec(), b"b".to_vec()]);
+
+        store.clear_signatures("tx").unwrap();
+        assert!(store.signatures("tx").unwrap().is_empty());
+        assert_eq!(store.signatures("other").unwrap(), vec![b"c".to_vec()]);
+    }
+}
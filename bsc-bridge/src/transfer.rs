@@ -0,0 +1,194 @@
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferKind {
+    /// Stellar payment to the bridge account, minted on BSC
+    Deposit,
+    /// BSC burn, paid out from the bridge account on Stellar
+    Withdraw,
+}
+
+/// Lifecycle of a transfer through the bridge.
+///
+/// ```text
+/// Detected -> Validated -> Signing -> Signed -> Submitted -> Confirmed
+///    |            ^            |          ^           |
+///    |            +------------+          +-----------+ (replaced or dropped)
+///    |            +------------------------------------+ (stale or reverted)
+///    +-> Refunding -> Refunded
+/// ```
+/// Every non final state can also move to `Failed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferState {
+    /// Seen on the source chain, not checked yet
+    Detected,
+    /// Checked and its parameters are known
+    Validated,
+    /// Collecting signatures from the other signers
+    Signing,
+    /// Enough signatures were collected
+    Signed,
+    /// Sent to the target chain, waiting for it to be included
+    Submitted,
+    Confirmed,
+    /// Invalid deposit which is being paid back to its sender
+    Refunding,
+    Refunded,
+    Failed,
+}
+
+impl TransferState {
+    pub fn is_final(self) -> bool {
+        matches!(
+            self,
+            TransferState::Confirmed | TransferState::Refunded | TransferState::Failed
+        )
+    }
+
+    pub fn can_transition_to(self, next: TransferState) -> bool {
+        use TransferState::*;
+
+        if self.is_final() {
+            return false;
+        }
+
+        matches!(
+            (self, next),
+            (_, Failed)
+                | (Detected, Validated)
+                | (Detected, Refunding)
+                | (Validated, Signing)
+                | (Signing, Signed)
+                | (Signed, Submitted)
+                | (Submitted, Confirmed)
+                // another signer already completed it on the target chain
+                | (Validated, Confirmed)
+                | (Signing, Confirmed)
+                // the submitted transaction was replaced or dropped, submit it again
+                | (Submitted, Signed)
+                // the Stellar transaction went stale, build and sign a new one
+                | (Signing, Validated)
+                | (Submitted, Validated)
+                | (Refunding, Refunded)
+        )
+    }
+}
+
+impl fmt::Display for TransferKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            TransferKind::Deposit => "deposit",
+            TransferKind::Withdraw => "withdraw",
+        };
+        f.write_str(kind)
+    }
+}
+
+impl FromStr for TransferKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deposit" => Ok(TransferKind::Deposit),
+            "withdraw" => Ok(TransferKind::Withdraw),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for TransferState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            TransferState::Detected => "detected",
+            TransferState::Validated => "validated",
+            TransferState::Signing => "signing",
+            TransferState::Signed => "signed",
+            TransferState::Submitted => "submitted",
+            TransferState::Confirmed => "confirmed",
+            TransferState::Refunding => "refunding",
+            TransferState::Refunded => "refunded",
+            TransferState::Failed => "failed",
+        };
+        f.write_str(state)
+    }
+}
+
+impl FromStr for TransferState {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "detected" => Ok(TransferState::Detected),
+            "validated" => Ok(TransferState::Validated),
+            "signing" => Ok(TransferState::Signing),
+            "signed" => Ok(TransferState::Signed),
+            "submitted" => Ok(TransferState::Submitted),
+            "confirmed" => Ok(TransferState::Confirmed),
+            "refunding" => Ok(TransferState::Refunding),
+            "refunded" => Ok(TransferState::Refunded),
+            "failed" => Ok(TransferState::Failed),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TransferState::*;
+
+    const STATES: [TransferState; 9] = [
+        Detected, Validated, Signing, Signed, Submitted, Confirmed, Refunding, Refunded, Failed,
+    ];
+
+    const ALLOWED: [(TransferState, TransferState); 18] = [
+        (Detected, Validated),
+        (Detected, Refunding),
+        (Detected, Failed),
+        (Validated, Signing),
+        (Validated, Confirmed),
+        (Validated, Failed),
+        (Signing, Validated),
+        (Signing, Signed),
+        (Signing, Confirmed),
+        (Signing, Failed),
+        (Signed, Submitted),
+        (Signed, Failed),
+        (Submitted, Validated),
+        (Submitted, Signed),
+        (Submitted, Confirmed),
+        (Submitted, Failed),
+        (Refunding, Refunded),
+        (Refunding, Failed),
+    ];
+
+    #[test]
+    fn only_listed_transitions_are_allowed() {
+        for from in STATES {
+            for to in STATES {
+                let expected = ALLOWED.contains(&(from, to));
+                assert_eq!(from.can_transition_to(to), expected, "{} -> {}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn stale_payout_goes_back_to_validated() {
+        assert!(Signing.can_transition_to(Validated));
+        assert!(Submitted.can_transition_to(Validated));
+    }
+
+    #[test]
+    fn final_states_never_move() {
+        for from in [Confirmed, Refunded, Failed] {
+            assert!(STATES.iter().all(|to| !from.can_transition_to(*to)));
+        }
+    }
+
+    #[test]
+    fn states_round_trip_through_strings() {
+        for state in STATES {
+            assert_eq!(state.to_string().parse(), Ok(state));
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use crate::{
+    bridge::Bridge,
+    mint::{classify, Deposit},
+    store::now,
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, str::FromStr, time::Duration};
+use tf_bsc::{types::H256, withdraw::Withdraw, Client as BscClient};
+use tf_stellar::{
+    fetch_transaction_time, fetch_transactions_with_memo,
+    payments::fetch_payments_for_transaction,
+    transaction::{
+        build_payment, decode_transaction, encode_transaction, expires_at, fetch_sequence,
+        has_min_fee,
+    },
+    Memo, PublicKey, Transaction,
+};
+
+// Name of the target chain in the withdraw events meant for Stellar
+const STELLAR_NETWORK: &str = "stellar";
+// The clocks of BSC and Stellar may disagree, look a bit further back than the source tx
+const CLOCK_MARGIN: Duration = Duration::from_secs(60 * 60);
+// A payout transaction which wasn't submitted in time expires and is rebuilt, so a
+// signature over it can't be used much later
+const PAYOUT_VALIDITY: Duration = Duration::from_secs(10 * 60);
+// Leeway for the clock of the node which built a payout
+const CLOCK_SKEW: Duration = Duration::from_secs(60);
+
+/// Parameters of a withdraw, as they are signed by every bridge signer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WithdrawParams {
+    /// Hex encoded hash of the BSC burn transaction, used as memo hash of the Stellar payment
+    pub burn_tx_hash: String,
+    /// Stellar address receiving the tokens
+    pub receiver: String,
+    /// Amount of tokens in stroops
+    pub amount: i64,
+    /// Base64 XDR of the payout transaction, once it is built
+    pub tx: Option<String>,
+}
+
+impl WithdrawParams {
+    /// Parameters of the payout for a BSC withdraw, `None` if it can't be paid out on Stellar.
+    pub fn from_event(withdraw: &Withdraw) -> Option<Self> {
+        if !withdraw.network.eq_ignore_ascii_case(STELLAR_NETWORK) {
+            return None;
+        }
+        PublicKey::from_account_id(&withdraw.receiver).ok()?;
+
+        // The token has the same 7 decimals on both chains
+        let amount = i64::try_from(withdraw.amount).ok()?;
+        if amount <= 0 {
+            return None;
+        }
+
+        Some(WithdrawParams {
+            burn_tx_hash: format!("{:?}", withdraw.tx_hash),
+            receiver: withdraw.receiver.clone(),
+            amount,
+            tx: None,
+        })
+    }
+
+    pub fn memo_hash(&self) -> Result<H256, Box<dyn Error>> {
+        Ok(H256::from_str(&self.burn_tx_hash)?)
+    }
+}
+
+/// Parameters of a refund of an invalid deposit, as they are signed by every bridge signer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefundParams {
+    /// Hash of the Stellar deposit transaction, used as return memo of the refund
+    pub deposit_tx_hash: String,
+    /// Sender of the deposit
+    pub receiver: String,
+    /// Amount of tokens in stroops
+    pub amount: i64,
+    /// Base64 XDR of the refund transaction, once it is built
+    pub tx: Option<String>,
+}
+
+/// A payment out of the bridge account, sent to the signers in a `StellarRequest`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Payout {
+    Withdraw(WithdrawParams),
+    Refund(RefundParams),
+}
+
+impl Payout {
+    /// Hash of the transaction which caused the payout
+    pub fn source_tx(&self) -> &str {
+        match self {
+            Payout::Withdraw(params) => &params.burn_tx_hash,
+            Payout::Refund(params) => &params.deposit_tx_hash,
+        }
+    }
+
+    pub fn receiver(&self) -> &str {
+        match self {
+            Payout::Withdraw(params) => &params.receiver,
+            Payout::Refund(params) => &params.receiver,
+        }
+    }
+
+    pub fn amount(&self) -> i64 {
+        match self {
+            Payout::Withdraw(params) => params.amount,
+            Payout::Refund(params) => params.amount,
+        }
+    }
+
+    /// The payout transaction, if it was built already.
+    pub fn transaction(&self) -> Result<Option<Transaction>, Box<dyn Error>> {
+        let tx = match self {
+            Payout::Withdraw(params) => &params.tx,
+            Payout::Refund(params) => &params.tx,
+        };
+        tx.as_deref().map(decode_transaction).transpose()
+    }
+
+    fn set_transaction(&mut self, tx: Option<String>) {
+        match self {
+            Payout::Withdraw(params) => params.tx = tx,
+            Payout::Refund(params) => params.tx = tx,
+        }
+    }
+
+    /// Memo linking the payout to its source transaction, a withdraw carries the
+    /// burn tx hash as hash memo and a refund the deposit tx hash as return memo.
+    pub fn memo(&self) -> Result<Memo, Box<dyn Error>> {
+        let hash = H256::from_str(self.source_tx())?;
+        Ok(match self {
+            Payout::Withdraw(_) => Memo::new_hash(hash.as_bytes())?,
+            Payout::Refund(_) => Memo::new_return(hash.as_bytes())?,
+        })
+    }
+
+    /// Build a new payout transaction from the current sequence of the bridge account.
+    pub async fn build(&mut self, bridge: &Bridge) -> Result<(), Box<dyn Error>> {
+        let asset = bridge.asset()?;
+        let memo = self.memo()?;
+        let tx = build_payment(
+            &bridge.account,
+            self.receiver(),
+            asset,
+            self.amount(),
+            memo,
+            PAYOUT_VALIDITY,
+            bridge.network.clone(),
+        )
+        .await?;
+
+        self.set_transaction(Some(encode_transaction(&tx)?));
+        Ok(())
+    }
+
+    /// Check that `tx` pays exactly this payout out of the bridge account.
+    pub fn matches(&self, tx: &Transaction, bridge: &Bridge) -> Result<bool, Box<dyn Error>> {
+        if tx.source_account().account_id() != bridge.account || *tx.memo() != self.memo()? {
+            return Ok(false);
+        }
+
+        let asset = bridge.asset()?;
+        Ok(match tx.operations().as_slice() {
+            [op] => op.as_payment().is_some_and(|payment| {
+                payment.source_account().is_none()
+                    && payment.destination().account_id() == self.receiver()
+                    && *payment.asset() == asset
+                    && payment.amount().to_i64() == self.amount()
+            }),
+            _ => false,
+        })
+    }
+
+    /// Unix time of the transaction which caused the payout, nothing older can pay it out.
+    async fn source_time(&self, bridge: &Bridge, bsc: &BscClient) -> Result<i64, Box<dyn Error>> {
+        match self {
+            Payout::Withdraw(params) => {
+                let burn_tx_hash = params.memo_hash()?;
+                let withdraw = bsc
+                    .withdraw(burn_tx_hash)
+                    .await?
+                    .ok_or_else(|| format!("no confirmed withdraw in {}", params.burn_tx_hash))?;
+                Ok(bsc.block_time(withdraw.block_number).await? as i64)
+            }
+            Payout::Refund(params) => Ok(fetch_transaction_time(
+                params.deposit_tx_hash.clone(),
+                bridge.network.clone(),
+            )
+            .await?),
+        }
+    }
+
+    /// Check on Horizon whether the bridge account already made this payout.
+    pub async fn is_paid(&self, bridge: &Bridge, bsc: &BscClient) -> Result<bool, Box<dyn Error>> {
+        let memo = self.memo()?;
+        let not_before = self.source_time(bridge, bsc).await? - CLOCK_MARGIN.as_secs() as i64;
+        let txs = fetch_transactions_with_memo(
+            bridge.account.clone(),
+            &memo,
+            not_before,
+            bridge.network.clone(),
+        )
+        .await?;
+
+        for tx in &txs {
+            if self.matches(tx, bridge)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Check that `params` match a confirmed withdraw on BSC.
+pub async fn verify_withdraw(
+    bsc: &BscClient,
+    params: &WithdrawParams,
+) -> Result<bool, Box<dyn Error>> {
+    let burn_tx_hash = params.memo_hash()?;
+    let withdraw = match bsc.withdraw(burn_tx_hash).await? {
+        Some(withdraw) => withdraw,
+        None => return Ok(false),
+    };
+
+    Ok(
+        WithdrawParams::from_event(&withdraw).is_some_and(|expected| {
+            expected.burn_tx_hash == params.burn_tx_hash
+                && expected.receiver == params.receiver
+                && expected.amount == params.amount
+        }),
+    )
+}
+
+/// Check that `params` match an invalid deposit on Stellar.
+pub async fn verify_refund(bridge: &Bridge, params: &RefundParams) -> Result<bool, Box<dyn Error>> {
+    let payments =
+        fetch_payments_for_transaction(params.deposit_tx_hash.clone(), bridge.network.clone())
+            .await?;
+
+    Ok(payments.iter().any(|p| match classify(bridge, p) {
+        Deposit::Invalid(expected) => {
+            expected.receiver == params.receiver && expected.amount == params.amount
+        }
+        _ => false,
+    }))
+}
+
+/// Whether `tx` can't be included in a ledger anymore.
+pub fn is_expired(tx: &Transaction) -> bool {
+    expires_at(tx).is_some_and(|at| at <= now())
+}
+
+/// Check that `tx` can only go through as the next transaction of the bridge account,
+/// within [`PAYOUT_VALIDITY`] and for the minimum fee.
+pub async fn is_next_payout(tx: &Transaction, bridge: &Bridge) -> Result<bool, Box<dyn Error>> {
+    let latest = now() + (PAYOUT_VALIDITY + CLOCK_SKEW).as_secs() as i64;
+    let in_time = expires_at(tx).is_some_and(|at| at > now() && at <= latest);
+    if !in_time || !has_min_fee(tx) {
+        return Ok(false);
+    }
+
+    let account = PublicKey::from_account_id(&bridge.account)?;
+    let sequence = fetch_sequence(&account, bridge.network.clone()).await?;
+    Ok(*tx.sequence() == sequence + 1)
+}
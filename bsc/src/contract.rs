@@ -0,0 +1,17 @@
+use ethers::contract::abigen;
+
+// Bindings for the subset of the TFT token contract the bridge interacts with.
+// Mints carry the Stellar deposit tx hash as `txid` and are only accepted by the
+// contract when enough signatures of the bridge signers are attached. Withdraws
+// burn the tokens and name the Stellar address to pay out in `blockchain_address`.
+// `getSigners` lists the addresses whose mint signatures the contract accepts.
+abigen!(
+    TftBridge,
+    r#"[
+        struct Signature { uint8 v; bytes32 r; bytes32 s; }
+        function mintTokens(address receiver, uint256 tokens, string txid, Signature[] signatures)
+        function isMintID(string txid) external view returns (bool)
+        function getSigners() external view returns (address[])
+        event Withdraw(address indexed receiver, uint256 tokens, string blockchain_address, string network)
+    ]"#
+);
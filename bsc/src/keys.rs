@@ -0,0 +1,132 @@
+use async_trait::async_trait;
+use ethers::{
+    signers::{to_eip155_v, LocalWallet, Signer},
+    types::{
+        transaction::{eip2718::TypedTransaction, eip712::Eip712},
+        Address, Signature, H256,
+    },
+    utils::hash_message,
+};
+use std::{fmt, str::FromStr, sync::Arc};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum KeyError {
+    #[error("invalid key: {0}")]
+    InvalidKey(String),
+    #[error("key backend failed to sign: {0}")]
+    Backend(String),
+}
+
+/// A secp256k1 key which signs hashes without handing out its secret, so it can
+/// live in memory as well as in a separate signing service.
+#[async_trait]
+pub trait KeyBackend: Send + Sync {
+    fn address(&self) -> Address;
+
+    /// Sign a hash, `v` of the signature is the recovery id plus 27.
+    async fn sign_hash(&self, hash: H256) -> Result<Signature, KeyError>;
+}
+
+/// Key held in memory by this process.
+pub struct LocalKey {
+    wallet: LocalWallet,
+}
+
+impl LocalKey {
+    pub fn new(wallet: LocalWallet) -> Self {
+        LocalKey { wallet }
+    }
+
+    /// Key from its hex encoded secret, with or without 0x prefix.
+    pub fn from_secret(secret: &str) -> Result<Self, KeyError> {
+        let wallet = LocalWallet::from_str(secret.trim_start_matches("0x"))
+            .map_err(|e| KeyError::InvalidKey(e.to_string()))?;
+        Ok(LocalKey { wallet })
+    }
+}
+
+#[async_trait]
+impl KeyBackend for LocalKey {
+    fn address(&self) -> Address {
+        self.wallet.address()
+    }
+
+    async fn sign_hash(&self, hash: H256) -> Result<Signature, KeyError> {
+        self.wallet
+            .sign_hash(hash)
+            .map_err(|e| KeyError::Backend(e.to_string()))
+    }
+}
+
+/// Signs the transactions sent by the client with a `KeyBackend`.
+#[derive(Clone)]
+pub struct KeySigner {
+    key: Arc<dyn KeyBackend>,
+    chain_id: u64,
+}
+
+impl KeySigner {
+    pub fn new(key: Arc<dyn KeyBackend>, chain_id: u64) -> Self {
+        KeySigner { key, chain_id }
+    }
+}
+
+impl fmt::Debug for KeySigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeySigner")
+            .field("address", &self.key.address())
+            .field("chain_id", &self.chain_id)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Signer for KeySigner {
+    type Error = KeyError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        self.key.sign_hash(hash_message(message)).await
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        // Same as `LocalWallet`: the chain id of the sighash must match the one in `v`
+        let chain_id = tx.chain_id().map(|id| id.as_u64()).unwrap_or(self.chain_id);
+        let mut tx = tx.clone();
+        tx.set_chain_id(chain_id);
+
+        let mut signature = self.key.sign_hash(tx.sighash()).await?;
+        let recovery_id = match signature.v {
+            27 | 28 => signature.v as u8 - 27,
+            v => return Err(KeyError::Backend(format!("invalid signature v {}", v))),
+        };
+        signature.v = to_eip155_v(recovery_id, chain_id);
+        Ok(signature)
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        let hash = payload
+            .encode_eip712()
+            .map_err(|e| KeyError::InvalidKey(e.to_string()))?;
+        self.key.sign_hash(H256::from(hash)).await
+    }
+
+    fn address(&self) -> Address {
+        self.key.address()
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    fn with_chain_id<T: Into<u64>>(mut self, chain_id: T) -> Self {
+        self.chain_id = chain_id.into();
+        self
+    }
+}
@@ -0,0 +1,165 @@
+use contract::TftBridge;
+pub use ethers::types;
+use ethers::{
+    middleware::SignerMiddleware,
+    providers::{Http, Middleware, Provider, ProviderError},
+    signers::{LocalWallet, Signer, WalletError},
+    types::{Address, BlockNumber, H256, U256},
+};
+use keys::{KeyBackend, KeyError, KeySigner, LocalKey};
+use std::{str::FromStr, sync::Arc, sync::Mutex, time::Duration};
+use thiserror::Error;
+pub mod contract;
+pub mod keys;
+pub mod mint;
+pub mod withdraw;
+
+const DEFAULT_CONFIRMATIONS: usize = 3;
+// BSC produces a block every ~3 seconds, polling faster than that is pointless
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+pub type BscMiddleware = SignerMiddleware<Provider<Http>, KeySigner>;
+
+#[derive(Error, Debug)]
+pub enum BscError {
+    #[error("invalid rpc url: {0}")]
+    InvalidUrl(String),
+    #[error("invalid address: {0}")]
+    InvalidAddress(String),
+    #[error("invalid key: {0}")]
+    InvalidKey(#[from] WalletError),
+    #[error("key error: {0}")]
+    Key(#[from] KeyError),
+    #[error("invalid signature at index {0}")]
+    InvalidSignature(usize),
+    #[error("gas price {0} exceeds the configured maximum {1}")]
+    GasPriceTooHigh(U256, U256),
+    #[error("provider error: {0}")]
+    Provider(#[from] ProviderError),
+    #[error("rpc error: {0}")]
+    Rpc(String),
+    #[error("contract error: {0}")]
+    Contract(String),
+    #[error("transaction {0:?} was dropped from the mempool")]
+    Dropped(H256),
+}
+
+/// Transaction envelope used when submitting to the chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxType {
+    Legacy,
+    Eip1559,
+}
+
+pub struct Client {
+    key: Arc<dyn KeyBackend>,
+    provider: Arc<BscMiddleware>,
+    contract: TftBridge<BscMiddleware>,
+    tx_type: TxType,
+    confirmations: usize,
+    max_gas_price: Option<U256>,
+    // Next nonce to use, `None` means it has to be fetched from the chain first
+    nonce: Mutex<Option<U256>>,
+}
+
+/// Generate a new key, returns its hex encoded secret and its address.
+pub fn generate_key() -> (String, Address) {
+    let wallet = LocalWallet::new(&mut ethers::core::rand::thread_rng());
+    let secret = ethers::utils::hex::encode(wallet.signer().to_bytes());
+    (format!("0x{}", secret), wallet.address())
+}
+
+impl Client {
+    pub fn new(
+        rpc_url: &str,
+        contract_address: &str,
+        chain_id: u64,
+        secret: &str,
+        tx_type: TxType,
+    ) -> Result<Self, BscError> {
+        let wallet = LocalWallet::from_str(secret.trim_start_matches("0x"))?;
+        let key = Arc::new(LocalKey::new(wallet));
+        Self::with_key(rpc_url, contract_address, chain_id, key, tx_type)
+    }
+
+    /// Client signing through `key`, which may be held outside of this process.
+    pub fn with_key(
+        rpc_url: &str,
+        contract_address: &str,
+        chain_id: u64,
+        key: Arc<dyn KeyBackend>,
+        tx_type: TxType,
+    ) -> Result<Self, BscError> {
+        let provider = Provider::<Http>::try_from(rpc_url)
+            .map_err(|_| BscError::InvalidUrl(rpc_url.to_string()))?
+            .interval(POLL_INTERVAL);
+        let address = Address::from_str(contract_address)
+            .map_err(|_| BscError::InvalidAddress(contract_address.to_string()))?;
+
+        let signer = KeySigner::new(key.clone(), chain_id);
+        let provider = Arc::new(SignerMiddleware::new(provider, signer));
+        let contract = TftBridge::new(address, provider.clone());
+
+        Ok(Client {
+            key,
+            provider,
+            contract,
+            tx_type,
+            confirmations: DEFAULT_CONFIRMATIONS,
+            max_gas_price: None,
+            nonce: Mutex::new(None),
+        })
+    }
+
+    /// Number of blocks to wait for on top of the one including a transaction.
+    pub fn with_confirmations(mut self, confirmations: usize) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    /// Refuse to submit transactions when the network asks for more than `max` wei per gas.
+    pub fn with_max_gas_price(mut self, max: U256) -> Self {
+        self.max_gas_price = Some(max);
+        self
+    }
+
+    pub fn address(&self) -> Address {
+        self.key.address()
+    }
+
+    async fn next_nonce(&self) -> Result<U256, BscError> {
+        if let Some(nonce) = self.take_nonce() {
+            return Ok(nonce);
+        }
+
+        let fetched = self
+            .provider
+            .get_transaction_count(self.address(), Some(BlockNumber::Pending.into()))
+            .await
+            .map_err(|e| BscError::Rpc(e.to_string()))?;
+
+        let mut nonce = self.nonce.lock().expect("nonce lock poisoned");
+        let next = nonce.unwrap_or(fetched);
+        *nonce = Some(next + 1);
+        Ok(next)
+    }
+
+    fn take_nonce(&self) -> Option<U256> {
+        let mut nonce = self.nonce.lock().expect("nonce lock poisoned");
+        let next = (*nonce)?;
+        *nonce = Some(next + 1);
+        Some(next)
+    }
+
+    // Forget the local nonce so the next transaction picks it up from the chain again
+    fn reset_nonce(&self) {
+        *self.nonce.lock().expect("nonce lock poisoned") = None;
+    }
+
+    fn check_gas_price(&self, price: U256) -> Result<(), BscError> {
+        match self.max_gas_price {
+            Some(max) if price > max => Err(BscError::GasPriceTooHigh(price, max)),
+            _ => Ok(()),
+        }
+    }
+}
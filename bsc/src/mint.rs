@@ -0,0 +1,246 @@
+use crate::{contract, BscError, Client, TxType};
+use ethers::{
+    abi::{encode_packed, Detokenize, Token},
+    contract::{ContractCall, ContractError},
+    providers::Middleware,
+    types::{Address, BlockNumber, Signature, H256, U256},
+    utils::{hash_message, keccak256},
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+/// Parameters of a mint, as they are signed by every bridge signer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MintParams {
+    pub receiver: Address,
+    pub amount: U256,
+    /// Hash of the Stellar deposit transaction
+    pub tx_id: String,
+}
+
+impl MintParams {
+    /// Hash the contract recovers the signers from: `keccak256(abi.encodePacked(receiver, tokens, txid))`.
+    pub fn hash(&self) -> [u8; 32] {
+        let packed = encode_packed(&[
+            Token::Address(self.receiver),
+            Token::Uint(self.amount),
+            Token::String(self.tx_id.clone()),
+        ])
+        .expect("mint params can always be packed");
+        keccak256(packed)
+    }
+
+    /// Address which produced `signature` over these parameters, recovered the way
+    /// the contract does it. `None` if the signature is malformed.
+    pub fn recover_signer(&self, signature: &[u8]) -> Option<Address> {
+        let signature = Signature::try_from(signature).ok()?;
+        // The contract passes v to ecrecover as is, which only takes 27 or 28
+        if !matches!(signature.v, 27 | 28) {
+            return None;
+        }
+        signature.recover(hash_message(self.hash())).ok()
+    }
+}
+
+/// Final state of a submitted mint transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MintOutcome {
+    Confirmed {
+        tx_hash: H256,
+        block_number: u64,
+    },
+    /// The call reverted, either while simulating it (no `tx_hash`) or on chain.
+    Reverted {
+        tx_hash: Option<H256>,
+        reason: Option<String>,
+    },
+    /// The contract already used the deposit tx hash for a previous mint.
+    AlreadyMinted,
+    /// Our transaction disappeared and its nonce was consumed by another one.
+    Replaced {
+        tx_hash: H256,
+        nonce: U256,
+    },
+}
+
+impl Client {
+    /// Ask the contract whether the Stellar deposit `tx_id` was already minted.
+    pub async fn is_minted(&self, tx_id: &str) -> Result<bool, BscError> {
+        self.contract
+            .is_mint_id(tx_id.to_string())
+            .call()
+            .await
+            .map_err(|e| BscError::Contract(e.to_string()))
+    }
+
+    /// Addresses whose signatures the contract accepts for a mint.
+    pub async fn signers(&self) -> Result<Vec<Address>, BscError> {
+        self.contract
+            .get_signers()
+            .call()
+            .await
+            .map_err(|e| BscError::Contract(e.to_string()))
+    }
+
+    /// Sign the mint parameters with the BSC key of this signer.
+    /// The result is the 65 bytes `r || s || v` signature the contract expects.
+    pub async fn sign_mint(&self, params: &MintParams) -> Result<Vec<u8>, BscError> {
+        let signature = self.key.sign_hash(hash_message(params.hash())).await?;
+        Ok(signature.to_vec())
+    }
+
+    /// Submit a mint with the signatures collected from the other signers and
+    /// wait for it to be confirmed.
+    pub async fn submit_mint(
+        &self,
+        params: &MintParams,
+        signatures: &[Vec<u8>],
+    ) -> Result<MintOutcome, BscError> {
+        if self.is_minted(&params.tx_id).await? {
+            info!("mint {} was already executed", params.tx_id);
+            return Ok(MintOutcome::AlreadyMinted);
+        }
+
+        let signatures = signatures
+            .iter()
+            .enumerate()
+            .map(|(i, s)| to_contract_signature(s).ok_or(BscError::InvalidSignature(i)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let call = self.contract.mint_tokens(
+            params.receiver,
+            params.amount,
+            params.tx_id.clone(),
+            signatures,
+        );
+
+        // Dry run first so a mint that would revert doesn't cost any gas
+        if let Err(e) = call.call().await {
+            let reason = revert_reason(&e);
+            warn!("mint {} would revert: {:?}", params.tx_id, reason);
+            return Ok(MintOutcome::Reverted {
+                tx_hash: None,
+                reason,
+            });
+        }
+
+        // Price the call before taking a nonce, a nonce taken and never sent leaves a gap
+        let call = self.with_gas_price(call).await?;
+        let nonce = self.next_nonce().await?;
+        let call = call.nonce(nonce);
+
+        let pending = match call.send().await {
+            Ok(pending) => pending,
+            Err(e) => {
+                self.reset_nonce();
+                return Err(BscError::Contract(e.to_string()));
+            }
+        };
+        let tx_hash = pending.tx_hash();
+        info!("mint {} submitted in tx {:?}", params.tx_id, tx_hash);
+
+        let receipt = pending.confirmations(self.confirmations).await?;
+
+        match receipt {
+            Some(receipt) if receipt.status == Some(1.into()) => {
+                let block_number = receipt.block_number.unwrap_or_default().as_u64();
+                info!("mint {} confirmed in block {}", params.tx_id, block_number);
+                Ok(MintOutcome::Confirmed {
+                    tx_hash,
+                    block_number,
+                })
+            }
+            Some(receipt) => {
+                // Replay the call on the block it failed in to recover the revert reason
+                let reason = match receipt.block_number {
+                    Some(block) => call
+                        .block(block)
+                        .call()
+                        .await
+                        .err()
+                        .and_then(|e| revert_reason(&e)),
+                    None => None,
+                };
+                warn!(
+                    "mint {} reverted in tx {:?}: {:?}",
+                    params.tx_id, tx_hash, reason
+                );
+                Ok(MintOutcome::Reverted {
+                    tx_hash: Some(tx_hash),
+                    reason,
+                })
+            }
+            None => {
+                self.reset_nonce();
+                let mined_nonce = self
+                    .provider
+                    .get_transaction_count(self.address(), Some(BlockNumber::Latest.into()))
+                    .await
+                    .map_err(|e| BscError::Rpc(e.to_string()))?;
+
+                if mined_nonce > nonce {
+                    warn!("mint {} tx {:?} was replaced", params.tx_id, tx_hash);
+                    Ok(MintOutcome::Replaced { tx_hash, nonce })
+                } else {
+                    Err(BscError::Dropped(tx_hash))
+                }
+            }
+        }
+    }
+
+    async fn with_gas_price<D: Detokenize>(
+        &self,
+        call: ContractCall<crate::BscMiddleware, D>,
+    ) -> Result<ContractCall<crate::BscMiddleware, D>, BscError> {
+        match self.tx_type {
+            TxType::Legacy => {
+                let gas_price = self
+                    .provider
+                    .get_gas_price()
+                    .await
+                    .map_err(|e| BscError::Rpc(e.to_string()))?;
+                self.check_gas_price(gas_price)?;
+                debug!("using legacy gas price {}", gas_price);
+                Ok(call.legacy().gas_price(gas_price))
+            }
+            TxType::Eip1559 => {
+                let (max_fee, priority_fee) = self
+                    .provider
+                    .estimate_eip1559_fees(None)
+                    .await
+                    .map_err(|e| BscError::Rpc(e.to_string()))?;
+                self.check_gas_price(max_fee)?;
+                debug!(
+                    "using max fee {} and priority fee {}",
+                    max_fee, priority_fee
+                );
+
+                let mut call = call;
+                if let Some(tx) = call.tx.as_eip1559_mut() {
+                    tx.max_fee_per_gas = Some(max_fee);
+                    tx.max_priority_fee_per_gas = Some(priority_fee);
+                }
+                Ok(call)
+            }
+        }
+    }
+}
+
+fn to_contract_signature(bytes: &[u8]) -> Option<contract::Signature> {
+    let signature = Signature::try_from(bytes).ok()?;
+
+    let mut r = [0u8; 32];
+    let mut s = [0u8; 32];
+    signature.r.to_big_endian(&mut r);
+    signature.s.to_big_endian(&mut s);
+
+    Some(contract::Signature {
+        v: u8::try_from(signature.v).ok()?,
+        r,
+        s,
+    })
+}
+
+fn revert_reason<M: Middleware>(e: &ContractError<M>) -> Option<String> {
+    e.decode_revert::<String>()
+}
@@ -0,0 +1,103 @@
+use crate::{contract::WithdrawFilter, BscError, Client};
+use ethers::{
+    contract::parse_log,
+    providers::Middleware,
+    types::{H256, U256},
+};
+use serde::{Deserialize, Serialize};
+
+/// Tokens burned on BSC, to be paid out on another chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Withdraw {
+    pub tx_hash: H256,
+    pub block_number: u64,
+    /// Address on the target chain, a Stellar address for withdraws to Stellar
+    pub receiver: String,
+    /// Name of the target chain
+    pub network: String,
+    pub amount: U256,
+}
+
+impl Client {
+    pub async fn latest_block(&self) -> Result<u64, BscError> {
+        let block = self
+            .provider
+            .get_block_number()
+            .await
+            .map_err(|e| BscError::Rpc(e.to_string()))?;
+        Ok(block.as_u64())
+    }
+
+    /// Unix time of `block`, in seconds.
+    pub async fn block_time(&self, block: u64) -> Result<u64, BscError> {
+        let block = self
+            .provider
+            .get_block(block)
+            .await
+            .map_err(|e| BscError::Rpc(e.to_string()))?
+            .ok_or_else(|| BscError::Rpc(format!("block {} not found", block)))?;
+        Ok(block.timestamp.as_u64())
+    }
+
+    /// Last block which has enough confirmations to be processed.
+    pub async fn confirmed_block(&self) -> Result<u64, BscError> {
+        Ok(self
+            .latest_block()
+            .await?
+            .saturating_sub(self.confirmations as u64))
+    }
+
+    /// Withdraws emitted by the contract between `from` and `to`, both inclusive.
+    pub async fn withdraws(&self, from: u64, to: u64) -> Result<Vec<Withdraw>, BscError> {
+        let events = self
+            .contract
+            .withdraw_filter()
+            .from_block(from)
+            .to_block(to)
+            .query_with_meta()
+            .await
+            .map_err(|e| BscError::Contract(e.to_string()))?;
+
+        Ok(events
+            .into_iter()
+            .map(|(event, meta)| Withdraw {
+                tx_hash: meta.transaction_hash,
+                block_number: meta.block_number.as_u64(),
+                receiver: event.blockchain_address,
+                network: event.network,
+                amount: event.tokens,
+            })
+            .collect())
+    }
+
+    /// Look up the withdraw emitted by `tx_hash`, as long as it is confirmed.
+    pub async fn withdraw(&self, tx_hash: H256) -> Result<Option<Withdraw>, BscError> {
+        let receipt = match self
+            .provider
+            .get_transaction_receipt(tx_hash)
+            .await
+            .map_err(|e| BscError::Rpc(e.to_string()))?
+        {
+            Some(receipt) if receipt.status == Some(1.into()) => receipt,
+            _ => return Ok(None),
+        };
+
+        let block_number = match receipt.block_number {
+            Some(block) if block.as_u64() <= self.confirmed_block().await? => block.as_u64(),
+            _ => return Ok(None),
+        };
+
+        Ok(receipt
+            .logs
+            .into_iter()
+            .filter(|log| log.address == self.contract.address())
+            .find_map(|log| parse_log::<WithdrawFilter>(log).ok())
+            .map(|event| Withdraw {
+                tx_hash,
+                block_number,
+                receiver: event.blockchain_address,
+                network: event.network,
+                amount: event.tokens,
+            }))
+    }
+}
@@ -0,0 +1,101 @@
+use libp2p::{
+    core::Endpoint,
+    swarm::{
+        dummy, ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, PollParameters,
+        THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+    },
+    Multiaddr, PeerId,
+};
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    task::{Context, Poll},
+};
+use thiserror::Error;
+use tracing::warn;
+
+#[derive(Error, Debug)]
+#[error("peer {0} is not in the allowlist")]
+pub struct NotAllowed(pub PeerId);
+
+/// Accepts connections only from the allowed peers, once a list was set. Until
+/// then every peer gets in.
+#[derive(Default)]
+pub struct Allowlist {
+    peers: Option<HashSet<PeerId>>,
+    // Peers let in whatever the list says, like the relays
+    always: HashSet<PeerId>,
+}
+
+impl Allowlist {
+    /// Replace the allowed peers, the connections of the others should be closed.
+    pub fn set(&mut self, peers: HashSet<PeerId>) {
+        self.peers = Some(peers);
+    }
+
+    /// Let `peer` in even when it isn't in the list.
+    pub fn always_allow(&mut self, peer: PeerId) {
+        self.always.insert(peer);
+    }
+
+    pub fn is_allowed(&self, peer: &PeerId) -> bool {
+        match &self.peers {
+            Some(peers) => peers.contains(peer) || self.always.contains(peer),
+            None => true,
+        }
+    }
+
+    fn enforce(&self, peer: PeerId, remote_addr: &Multiaddr) -> Result<(), ConnectionDenied> {
+        if self.is_allowed(&peer) {
+            return Ok(());
+        }
+        warn!(%peer, "rejected connection of a peer not in the allowlist on {}", remote_addr);
+        Err(ConnectionDenied::new(NotAllowed(peer)))
+    }
+}
+
+impl NetworkBehaviour for Allowlist {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type OutEvent = Infallible;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        peer: PeerId,
+        _: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.enforce(peer, remote_addr)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        peer: PeerId,
+        addr: &Multiaddr,
+        _: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.enforce(peer, addr)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, _: FromSwarm<Self::ConnectionHandler>) {}
+
+    fn on_connection_handler_event(
+        &mut self,
+        _: PeerId,
+        _: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut Context<'_>,
+        _: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Self::OutEvent, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
@@ -1,13 +1,27 @@
-use crate::types::{SignRequest, SignResponse};
+use crate::{
+    allowlist::Allowlist,
+    types::{SignResponse, SignedRequest},
+};
 use async_trait::async_trait;
 use either::Either;
-use futures::AsyncReadExt;
+use futures::{future, AsyncReadExt};
 use libp2p::{
-    core::{muxing::StreamMuxerBox, transport, transport::upgrade::Version},
+    autonat,
+    autonat::Event as AutonatEvent,
+    core::{
+        muxing::StreamMuxerBox,
+        transport,
+        transport::{upgrade::Version, OptionalTransport},
+    },
+    dcutr,
+    dcutr::Event as DcutrEvent,
+    dns::TokioDnsConfig,
     futures::{AsyncRead, AsyncWrite, AsyncWriteExt},
     identify,
     identify::Event as IdentifyEvent,
     identity::{Keypair, PeerId},
+    mdns,
+    mdns::Event as MdnsEvent,
     noise, ping,
     ping::Event as PingEvent,
     pnet::{PnetConfig, PreSharedKey},
@@ -18,20 +32,111 @@ use libp2p::{
         Config as RequestResponseConfig, Event as RequestResponseEvent, ProtocolName,
         ProtocolSupport,
     },
-    tcp,
+    swarm::behaviour::toggle::Toggle,
+    tcp, websocket,
+    websocket::WsConfig,
     yamux::YamuxConfig,
     Transport,
 };
+use libp2p_quic as quic;
 use libp2p_swarm_derive::NetworkBehaviour;
-use std::{io, iter::once, str::FromStr, time::Duration};
+use rustls_pemfile::Item;
+use std::{convert::Infallible, io, iter::once, str::FromStr, time::Duration};
+
+pub type BoxedTransport = transport::Boxed<(PeerId, StreamMuxerBox)>;
+
+/// Transports a host connects over, next to the relay which runs over them.
+#[derive(Debug, Clone)]
+pub struct Transports {
+    pub tcp: bool,
+    /// WebSocket over TCP, to listen on `/ws` and `/wss` addresses and dial them
+    pub websocket: bool,
+    /// Certificate of the `/wss` listen addresses
+    pub websocket_tls: Option<WebsocketTls>,
+    /// QUIC over UDP, on `/udp/<port>/quic-v1` addresses. Dials try it first as it
+    /// sets up a connection in a single round trip.
+    pub quic: bool,
+}
+
+impl Default for Transports {
+    fn default() -> Self {
+        Transports {
+            tcp: true,
+            websocket: false,
+            websocket_tls: None,
+            quic: false,
+        }
+    }
+}
+
+impl Transports {
+    fn any(&self) -> bool {
+        self.tcp || self.websocket
+    }
+}
+
+/// Certificate chain and private key served on `/wss` listen addresses.
+#[derive(Clone)]
+pub struct WebsocketTls {
+    certs: Vec<Vec<u8>>,
+    key: Vec<u8>,
+}
+
+impl WebsocketTls {
+    /// Read the certificate chain and its private key from PEM, as issued by
+    /// Let's Encrypt for instance.
+    pub fn from_pem(certs: &[u8], key: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let certs = rustls_pemfile::certs(&mut &certs[..])?;
+        if certs.is_empty() {
+            return Err("no certificate found".into());
+        }
+
+        let key = rustls_pemfile::read_all(&mut &key[..])?
+            .into_iter()
+            .find_map(|item| match item {
+                Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => Some(key),
+                _ => None,
+            })
+            .ok_or("no private key found")?;
+
+        Ok(WebsocketTls { certs, key })
+    }
+
+    fn config(&self) -> Result<websocket::tls::Config, websocket::tls::Error> {
+        websocket::tls::Config::new(
+            websocket::tls::PrivateKey::new(self.key.clone()),
+            self.certs
+                .iter()
+                .cloned()
+                .map(websocket::tls::Certificate::new),
+        )
+    }
+}
+
+// Keeps the private key out of the logs
+impl std::fmt::Debug for WebsocketTls {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebsocketTls")
+            .field("certs", &self.certs.len())
+            .finish_non_exhaustive()
+    }
+}
+
+const IDENTIFY_PROTOCOL: &str = "ipfs/0.1.0";
 
 #[derive(NetworkBehaviour)]
 #[behaviour(out_event = "Event")]
 pub struct Behaviour {
+    // First, so the connections of unknown peers are denied before the others see them
+    pub allowlist: Allowlist,
     pub relay: RelayBehaviour,
+    pub dcutr: dcutr::Behaviour,
+    pub autonat: autonat::Behaviour,
     pub ping: ping::Behaviour,
     pub identify: identify::Behaviour,
     pub request_response: RequestResponseBehaviour<ExchangeCodec>,
+    // Finds the nodes on the local network, off unless asked for
+    pub mdns: Toggle<mdns::tokio::Behaviour>,
 }
 
 impl Behaviour {
@@ -39,25 +144,48 @@ impl Behaviour {
         kp: &Keypair,
         peer_id: PeerId,
         psk: Option<String>,
-    ) -> Result<(Self, transport::Boxed<(PeerId, StreamMuxerBox)>), Box<dyn std::error::Error>>
-    {
-        let noise_config = noise::NoiseAuthenticated::xx(kp)?;
-        let yamux_config = YamuxConfig::default();
+        transports: &Transports,
+        mdns: bool,
+    ) -> Result<(Self, BoxedTransport), Box<dyn std::error::Error>> {
+        // The relay needs another transport to reach the relay itself
+        if !transports.any() && !transports.quic {
+            return Err("no transport enabled".into());
+        }
+        // QUIC brings its own TLS handshake, there is no stream to run pnet on
+        if transports.quic && psk.is_some() {
+            return Err(
+                "QUIC can't be used in a private network, turn it off or remove the swarm key"
+                    .into(),
+            );
+        }
+        let tcp = match transports.tcp {
+            true => OptionalTransport::some(tcp_transport()),
+            false => OptionalTransport::none(),
+        };
+        let websocket = match transports.websocket {
+            true => {
+                OptionalTransport::some(websocket_transport(transports.websocket_tls.as_ref())?)
+            }
+            false => OptionalTransport::none(),
+        };
 
-        let base_transport = tcp::async_io::Transport::new(tcp::Config::default().nodelay(true));
         let (relay_transport, behaviour) = relay::client::new(peer_id);
-
-        let transport = base_transport.or_transport(relay_transport).boxed();
-
-        let maybe_encrypted = match psk {
-            Some(psk) => {
-                let pk = PreSharedKey::from_str(psk.as_str())?;
-                Either::Left(
-                    transport.and_then(move |socket, _| PnetConfig::new(pk).handshake(socket)),
-                )
-            }
-            None => Either::Right(transport),
+        let transport = tcp
+            .or_transport(websocket)
+            .or_transport(relay_transport)
+            .boxed();
+        let transport = authenticate(kp, transport, psk)?;
+        let quic = match transports.quic {
+            true => OptionalTransport::some(quic_transport(kp)),
+            false => OptionalTransport::none(),
         };
+        // QUIC comes first so it's the one dialing `/quic-v1` addresses
+        let transport = quic
+            .or_transport(transport)
+            .map(|output, _| match output {
+                future::Either::Left(output) | future::Either::Right(output) => output,
+            })
+            .boxed();
 
         let request_response = RequestResponseBehaviour::new(
             ExchangeCodec,
@@ -65,33 +193,99 @@ impl Behaviour {
             RequestResponseConfig::default(),
         );
 
+        let mdns = match mdns {
+            true => Some(mdns::tokio::Behaviour::new(
+                mdns::Config::default(),
+                peer_id,
+            )?),
+            false => None,
+        };
+
         Ok((
             Behaviour {
+                allowlist: Allowlist::default(),
                 ping: ping::Behaviour::new(ping::Config::new()),
                 relay: behaviour,
+                dcutr: dcutr::Behaviour::new(peer_id),
+                autonat: autonat::Behaviour::new(peer_id, autonat::Config::default()),
                 // keep_alive: keep_alive::Behaviour,
-                identify: identify::Behaviour::new(identify::Config::new(
-                    "ipfs/0.1.0".to_string(),
-                    kp.public(),
-                )),
+                identify: new_identify(kp),
                 request_response,
+                mdns: mdns.into(),
             },
-            maybe_encrypted
-                .upgrade(Version::V1)
-                .authenticate(noise_config)
-                .multiplex(yamux_config)
-                .timeout(Duration::from_secs(20))
-                .boxed(),
+            transport,
         ))
     }
 }
 
+pub(crate) fn tcp_transport() -> tcp::async_io::Transport {
+    // Hole punching dials out from the port the node listens on
+    tcp::async_io::Transport::new(tcp::Config::default().nodelay(true).port_reuse(true))
+}
+
+// QUIC secures and multiplexes the connections itself
+fn quic_transport(kp: &Keypair) -> BoxedTransport {
+    quic::tokio::Transport::new(quic::Config::new(kp))
+        .map(|(peer_id, conn), _| (peer_id, StreamMuxerBox::new(conn)))
+        .boxed()
+}
+
+// Resolves the host names of /dns addresses, which wss needs to check the certificate
+fn websocket_transport(
+    tls: Option<&WebsocketTls>,
+) -> Result<WsConfig<TokioDnsConfig<tcp::async_io::Transport>>, Box<dyn std::error::Error>> {
+    let mut transport = WsConfig::new(TokioDnsConfig::system(tcp_transport())?);
+    if let Some(tls) = tls {
+        transport.set_tls_config(tls.config()?);
+    }
+    Ok(transport)
+}
+
+pub(crate) fn new_identify(kp: &Keypair) -> identify::Behaviour {
+    identify::Behaviour::new(identify::Config::new(
+        IDENTIFY_PROTOCOL.to_string(),
+        kp.public(),
+    ))
+}
+
+// Secure and multiplex the raw connections, inside the private network when a psk is set
+pub(crate) fn authenticate<S>(
+    kp: &Keypair,
+    transport: transport::Boxed<S>,
+    psk: Option<String>,
+) -> Result<BoxedTransport, Box<dyn std::error::Error>>
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    let noise_config = noise::NoiseAuthenticated::xx(kp)?;
+    let yamux_config = YamuxConfig::default();
+
+    let maybe_encrypted = match psk {
+        Some(psk) => {
+            let pk = PreSharedKey::from_str(psk.as_str())?;
+            Either::Left(transport.and_then(move |socket, _| PnetConfig::new(pk).handshake(socket)))
+        }
+        None => Either::Right(transport),
+    };
+
+    Ok(maybe_encrypted
+        .upgrade(Version::V1)
+        .authenticate(noise_config)
+        .multiplex(yamux_config)
+        .timeout(Duration::from_secs(20))
+        .boxed())
+}
+
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum Event {
     Identify(IdentifyEvent),
     Relay(RelayEvent),
+    Dcutr(DcutrEvent),
+    Autonat(AutonatEvent),
     Ping(PingEvent),
-    RequestResponse(RequestResponseEvent<SignRequest, SignResponse>),
+    RequestResponse(RequestResponseEvent<SignedRequest, SignResponse>),
+    Mdns(MdnsEvent),
 }
 
 impl From<IdentifyEvent> for Event {
@@ -106,18 +300,42 @@ impl From<RelayEvent> for Event {
     }
 }
 
+impl From<DcutrEvent> for Event {
+    fn from(event: DcutrEvent) -> Self {
+        Self::Dcutr(event)
+    }
+}
+
+impl From<AutonatEvent> for Event {
+    fn from(event: AutonatEvent) -> Self {
+        Self::Autonat(event)
+    }
+}
+
 impl From<PingEvent> for Event {
     fn from(event: PingEvent) -> Self {
         Self::Ping(event)
     }
 }
 
-impl From<RequestResponseEvent<SignRequest, SignResponse>> for Event {
-    fn from(event: RequestResponseEvent<SignRequest, SignResponse>) -> Self {
+impl From<RequestResponseEvent<SignedRequest, SignResponse>> for Event {
+    fn from(event: RequestResponseEvent<SignedRequest, SignResponse>) -> Self {
         Self::RequestResponse(event)
     }
 }
 
+impl From<Infallible> for Event {
+    fn from(event: Infallible) -> Self {
+        match event {}
+    }
+}
+
+impl From<MdnsEvent> for Event {
+    fn from(event: MdnsEvent) -> Self {
+        Self::Mdns(event)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ExchangeProtocol;
 
@@ -133,7 +351,7 @@ pub struct ExchangeCodec;
 #[async_trait]
 impl RequestResponseCodec for ExchangeCodec {
     type Protocol = ExchangeProtocol;
-    type Request = SignRequest;
+    type Request = SignedRequest;
     type Response = SignResponse;
 
     async fn read_request<T: Send + Unpin + AsyncRead>(
@@ -145,7 +363,7 @@ impl RequestResponseCodec for ExchangeCodec {
         io.read_to_end(&mut buff).await?;
 
         let req =
-            SignRequest::try_from(buff.as_slice()).map_err(|_| io::ErrorKind::InvalidInput)?;
+            SignedRequest::try_from(buff.as_slice()).map_err(|_| io::ErrorKind::InvalidInput)?;
         Ok(req)
     }
 
@@ -0,0 +1,163 @@
+use crate::{
+    behaviour::{Behaviour, Transports, WebsocketTls},
+    metrics::HostMetrics,
+    relays::Relays,
+    traits::Signer,
+    Libp2pHost, EVENT_CAPACITY,
+};
+use libp2p::{
+    identity::Keypair,
+    swarm::{AddressScore, SwarmBuilder},
+    Multiaddr, PeerId,
+};
+use std::{collections::HashMap, error::Error, sync::Arc};
+use tokio::sync::{broadcast, mpsc};
+use tracing::warn;
+
+// Used when no listen address is set, a random TCP port on every interface
+const DEFAULT_LISTEN_ADDRS: [&str; 2] = ["/ip4/0.0.0.0/tcp/0", "/ip6/::/tcp/0"];
+// And a random UDP port when QUIC is on
+const DEFAULT_QUIC_LISTEN_ADDRS: [&str; 2] =
+    ["/ip4/0.0.0.0/udp/0/quic-v1", "/ip6/::/udp/0/quic-v1"];
+
+/// Builds a [`Libp2pHost`], start with [`Libp2pHost::builder`].
+pub struct HostBuilder<S> {
+    signer: S,
+    keypair: Option<Keypair>,
+    psk: Option<String>,
+    listen_addrs: Vec<Multiaddr>,
+    external_addrs: Vec<Multiaddr>,
+    transports: Transports,
+    mdns: bool,
+}
+
+impl<S> HostBuilder<S>
+where
+    S: Signer,
+{
+    pub(crate) fn new(signer: S) -> Self {
+        HostBuilder {
+            signer,
+            keypair: None,
+            psk: None,
+            listen_addrs: Vec::new(),
+            external_addrs: Vec::new(),
+            transports: Transports::default(),
+            mdns: false,
+        }
+    }
+
+    /// Identity of the node, a new one is generated when not set.
+    pub fn keypair(mut self, keypair: Keypair) -> Self {
+        self.keypair = Some(keypair);
+        self
+    }
+
+    /// Key of the private network, the node only talks to peers holding the same key.
+    pub fn psk(mut self, psk: Option<String>) -> Self {
+        self.psk = psk;
+        self
+    }
+
+    /// Listen on `addr`, for instance `/ip6/::/tcp/4001`. Without any listen address
+    /// the node picks a random TCP port on every IPv4 and IPv6 interface.
+    pub fn listen_on(mut self, addr: Multiaddr) -> Self {
+        self.listen_addrs.push(addr);
+        self
+    }
+
+    /// Announce `addr` to the peers, when the node is reached on an address it
+    /// doesn't listen on itself, like a forwarded port.
+    pub fn external_address(mut self, addr: Multiaddr) -> Self {
+        self.external_addrs.push(addr);
+        self
+    }
+
+    /// Connect over TCP, on by default.
+    pub fn tcp(mut self, enabled: bool) -> Self {
+        self.transports.tcp = enabled;
+        self
+    }
+
+    /// Connect over WebSocket too, for hosts which only have HTTP ports open.
+    /// The node listens on it with `/tcp/<port>/ws` listen addresses.
+    pub fn websocket(mut self, enabled: bool) -> Self {
+        self.transports.websocket = enabled;
+        self
+    }
+
+    /// Serve `tls` on the `/tcp/<port>/wss` listen addresses, turns WebSocket on.
+    pub fn websocket_tls(mut self, tls: WebsocketTls) -> Self {
+        self.transports.websocket = true;
+        self.transports.websocket_tls = Some(tls);
+        self
+    }
+
+    /// Connect over QUIC too, preferred with the peers which support it. The node
+    /// listens on it with `/udp/<port>/quic-v1` listen addresses. QUIC can't be used
+    /// in a private network, building fails when a psk is set.
+    pub fn quic(mut self, enabled: bool) -> Self {
+        self.transports.quic = enabled;
+        self
+    }
+
+    /// Find and connect to the nodes on the local network with mDNS, to run a
+    /// federation on a LAN or a single host without any relay.
+    pub fn mdns(mut self, enabled: bool) -> Self {
+        self.mdns = enabled;
+        self
+    }
+
+    pub fn build(self) -> Result<Libp2pHost<S>, Box<dyn Error>> {
+        let kp = self.keypair.unwrap_or_else(Keypair::generate_ed25519);
+
+        let local_peer_id = PeerId::from(kp.public());
+        let (behaviour, transport) = Behaviour::new_behaviour_and_transport(
+            &kp,
+            local_peer_id,
+            self.psk,
+            &self.transports,
+            self.mdns,
+        )?;
+
+        let mut swarm =
+            SwarmBuilder::with_tokio_executor(transport, behaviour, local_peer_id).build();
+
+        if self.listen_addrs.is_empty() {
+            let quic_addrs = match self.transports.quic {
+                true => &DEFAULT_QUIC_LISTEN_ADDRS[..],
+                false => &[],
+            };
+            for addr in DEFAULT_LISTEN_ADDRS.iter().chain(quic_addrs) {
+                // Hosts without IPv6 still get the IPv4 port
+                if let Err(e) = swarm.listen_on(addr.parse()?) {
+                    warn!("failed to listen on {}: {}", addr, e);
+                }
+            }
+        }
+        for addr in self.listen_addrs {
+            swarm.listen_on(addr)?;
+        }
+        for addr in self.external_addrs {
+            swarm.add_external_address(addr, AddressScore::Infinite);
+        }
+
+        let (sign_outcomes, signed) = mpsc::unbounded_channel();
+        Ok(Libp2pHost {
+            identity: kp,
+            local_peer_id,
+            swarm,
+            signer: Arc::new(self.signer),
+            sign_outcomes,
+            signed,
+            responses: HashMap::default(),
+            relays: Relays::default(),
+            connections: HashMap::default(),
+            identified: HashMap::default(),
+            quic: self.transports.quic,
+            metrics: HostMetrics::default(),
+            swarm_metrics: None,
+            events: broadcast::channel(EVENT_CAPACITY).0,
+        })
+    }
+}
@@ -1,80 +1,233 @@
 use crate::{
-    traits::Signer,
-    types::{SignRequest, SignResponse},
+    traits::{RequestOrigin, Signer, SignerError},
+    types::{SignResponse, SignedRequest},
     Libp2pHost,
 };
-use libp2p::request_response::{Event as RequestResponseEvent, Message as RequestResponseMessage};
-use log::{debug, error, info};
+use libp2p::{
+    autonat::NatStatus,
+    request_response::{
+        Event as RequestResponseEvent, Message as RequestResponseMessage, RequestId,
+        ResponseChannel,
+    },
+    Multiaddr, PeerId,
+};
 use thiserror::Error;
+use tracing::{debug, error, info, info_span, warn, Instrument, Span};
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum SignRequestResponseError {
     #[error("transaction not found")]
     NotFound(String),
-    #[error("failed to sign")]
-    FailedToSign(String),
-    #[error("failed to send")]
-    FailedToSend,
+    #[error("failed to sign request {request_id} of {peer}: {reason}")]
+    FailedToSign {
+        peer: PeerId,
+        request_id: RequestId,
+        reason: String,
+    },
+    #[error("failed to send the response to request {request_id} of {peer}, the peer is gone")]
+    FailedToSend { peer: PeerId, request_id: RequestId },
+    #[error("response of {peer} to request {request_id} came in after the round ended")]
+    RoundEnded { peer: PeerId, request_id: RequestId },
     #[error("unknown error")]
     Unknown,
 }
 
+/// Something that happened in the swarm task, for the application to react on.
+#[derive(Debug, Clone)]
+pub enum HostEvent {
+    /// First connection to a peer was established
+    PeerConnected {
+        peer: PeerId,
+        address: Multiaddr,
+    },
+    /// Last connection to a peer was closed
+    PeerDisconnected {
+        peer: PeerId,
+    },
+    /// The relay accepted a reservation, peers can reach this node through it
+    RelayReservationAccepted {
+        relay: PeerId,
+        renewal: bool,
+    },
+    /// The reservation on the relay failed or its connection closed
+    RelayReservationLost {
+        relay: PeerId,
+    },
+    /// Every relay failed in a row, the node retries them with a growing backoff
+    NoRelayAvailable,
+    NewListenAddr {
+        address: Multiaddr,
+    },
+    /// AutoNAT changed its mind on whether the node is publicly reachable
+    ReachabilityChanged {
+        status: NatStatus,
+    },
+    /// A peer met through the relay is now also connected directly
+    DirectConnectionUpgraded {
+        peer: PeerId,
+    },
+    /// Hole punching to a peer failed, it stays reachable through the relay only
+    DirectConnectionUpgradeFailed {
+        peer: PeerId,
+        error: String,
+    },
+    /// A sign request of a peer was answered
+    RequestServed {
+        peer: PeerId,
+        request_id: RequestId,
+    },
+    /// A sign request to a peer failed, it won't answer this round
+    OutboundFailure {
+        peer: PeerId,
+        request_id: RequestId,
+        error: String,
+    },
+    /// Handling a request or a response failed, the swarm keeps running
+    Error(SignRequestResponseError),
+}
+
+/// Outcome of signing a request of a peer, handed back to the swarm task to answer it.
+pub(crate) struct SignOutcome {
+    peer: PeerId,
+    request_id: RequestId,
+    channel: ResponseChannel<SignResponse>,
+    result: Result<SignResponse, SignerError>,
+    span: Span,
+}
+
 pub async fn handle_request_response<S>(
     node: &mut Libp2pHost<S>,
-    event: RequestResponseEvent<SignRequest, SignResponse>,
+    event: RequestResponseEvent<SignedRequest, SignResponse>,
 ) -> Result<(), SignRequestResponseError>
 where
     S: Signer,
 {
     match event {
-        RequestResponseEvent::Message { message, .. } => match message {
+        RequestResponseEvent::Message { peer, message } => match message {
             RequestResponseMessage::Request {
                 request,
                 channel,
-                request_id: _req_id,
+                request_id,
             } => {
-                info!("request response 'Message::Request' for {:?}", request);
-
-                let response = node
-                    .signer
-                    .sign(&request)
-                    .map_err(|e| SignRequestResponseError::FailedToSign(e.to_string()))?;
+                let span = info_span!(
+                    "sign_request",
+                    %peer,
+                    %request_id,
+                    kind = request.request.kind(),
+                    signed = request.signature.is_some()
+                );
+                info!(parent: &span, "received sign request");
 
-                debug!("request is signed: {:?}", response);
-                node.swarm
-                    .behaviour_mut()
-                    .request_response
-                    .send_response(channel, response)
-                    .map_err(|_| SignRequestResponseError::FailedToSend)?;
+                let origin = RequestOrigin {
+                    peer,
+                    signature: request.signature,
+                };
+                // Checking a request against the chains takes a while, the swarm
+                // keeps going meanwhile and answers once the outcome comes back
+                let signer = node.signer.clone();
+                let outcomes = node.sign_outcomes.clone();
+                tokio::spawn(async move {
+                    let result = signer
+                        .sign(&origin, &request.request)
+                        .instrument(span.clone())
+                        .await;
+                    // Only fails when the swarm task stopped, nobody can be answered then
+                    let _ = outcomes.send(SignOutcome {
+                        peer,
+                        request_id,
+                        channel,
+                        result,
+                        span,
+                    });
+                });
             }
             RequestResponseMessage::Response {
                 request_id,
                 response,
             } => {
-                info!(
-                    "Request response 'Message::Response': {} {:?}",
-                    request_id, response
-                );
                 // Gather responses
-                if let Some(tx) = node.responses.remove(&request_id) {
-                    tx.send(response).unwrap();
+                if let Some(pending) = node.responses.remove(&request_id) {
+                    let latency = pending.sent_at.elapsed();
+                    info!(
+                        parent: &pending.span,
+                        %peer,
+                        %request_id,
+                        latency_ms = latency.as_millis() as u64,
+                        "received response of {} bytes",
+                        response.len()
+                    );
+                    node.metrics.response(&pending.peer, latency);
+                    // The requester stops listening once it has enough responses or timed out
+                    pending
+                        .tx
+                        .send(response)
+                        .map_err(|_| SignRequestResponseError::RoundEnded { peer, request_id })?;
+                } else {
+                    debug!(%peer, %request_id, "dropping response to an unknown request");
                 }
             }
         },
         RequestResponseEvent::OutboundFailure {
-            request_id, error, ..
+            peer,
+            request_id,
+            error,
         } => {
-            error!(
-                "Request {} response outbound failure {:?}",
-                request_id, error
-            );
+            match node.responses.remove(&request_id) {
+                Some(pending) => {
+                    warn!(parent: &pending.span, %peer, %request_id, "sign request failed: {}", error)
+                }
+                None => warn!(%peer, %request_id, "sign request failed: {}", error),
+            }
+            node.emit(HostEvent::OutboundFailure {
+                peer,
+                request_id,
+                error: error.to_string(),
+            });
         }
-        RequestResponseEvent::InboundFailure { error, .. } => {
-            error!("Request response inbound failure {:?}", error);
+        RequestResponseEvent::InboundFailure {
+            peer,
+            request_id,
+            error,
+        } => {
+            error!(%peer, %request_id, "failed to answer sign request: {}", error);
         }
         RequestResponseEvent::ResponseSent { request_id, peer } => {
-            info!("Request response response sent {} {:?}", request_id, peer);
+            debug!(%peer, %request_id, "response sent");
+            node.emit(HostEvent::RequestServed { peer, request_id });
         }
     }
     Ok(())
 }
+
+/// Answer the peer with the outcome of signing its request.
+pub(crate) fn send_sign_response<S>(
+    node: &mut Libp2pHost<S>,
+    outcome: SignOutcome,
+) -> Result<(), SignRequestResponseError>
+where
+    S: Signer,
+{
+    let SignOutcome {
+        peer,
+        request_id,
+        channel,
+        result,
+        span,
+    } = outcome;
+
+    let response = result.map_err(|e| {
+        warn!(parent: &span, "refused to sign: {}", e);
+        SignRequestResponseError::FailedToSign {
+            peer,
+            request_id,
+            reason: e.to_string(),
+        }
+    })?;
+
+    debug!(parent: &span, "signed request, {} bytes", response.len());
+    node.swarm
+        .behaviour_mut()
+        .request_response
+        .send_response(channel, response)
+        .map_err(|_| SignRequestResponseError::FailedToSend { peer, request_id })
+}
@@ -1,75 +1,275 @@
 use behaviour::{Behaviour, Event};
-use event::handle_request_response;
+pub use behaviour::{Transports, WebsocketTls};
+pub use builder::HostBuilder;
 use event::*;
+use event::{handle_request_response, send_sign_response, SignOutcome};
 use futures::prelude::*;
 use libp2p::{
-    identity::{Keypair, PeerId},
+    autonat::Event as AutonatEvent, dcutr::Event as DcutrEvent, identify::Event as IdentifyEvent,
+    mdns::Event as MdnsEvent,
+};
+pub use libp2p::{autonat::NatStatus, identity::Keypair, multiaddr, Multiaddr, PeerId};
+use libp2p::{
+    metrics::{Metrics as SwarmMetrics, Recorder},
     multiaddr::Protocol,
+    pnet::PreSharedKey,
+    relay::client::Event as RelayEvent,
     request_response::RequestId,
-    swarm::{Swarm, SwarmBuilder, SwarmEvent, THandlerErr},
-    Multiaddr,
+    swarm::{
+        dial_opts::{DialOpts, PeerCondition},
+        AddressScore, Swarm, SwarmEvent, THandlerErr,
+    },
+};
+use metrics::{HostMetrics, RoundOutcome};
+pub use prometheus_client::registry::Registry;
+use relays::Relays;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
-use log::{debug, error, info};
-use std::collections::HashMap;
-use std::{error::Error, fs, path::Path};
-use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::{
+    sync::{
+        broadcast,
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+    task::JoinHandle,
+    time,
+};
+use tracing::{debug, error, info, instrument, warn, Span};
 use traits::Signer;
-use types::{SignRequest, SignResponse};
+use types::{SignRequest, SignResponse, SignedRequest};
 
+pub mod allowlist;
 pub mod behaviour;
+mod builder;
 pub mod event;
+pub mod metrics;
+pub mod relay;
+mod relays;
 pub mod traits;
 pub mod types;
 
+const SWARM_KEY_FILE: &str = "swarm.key";
+// How long peers get to answer a sign request
+const ROUND_TIMEOUT: Duration = Duration::from_secs(10);
+// How long pending requests get to finish on shutdown
+const DRAIN_TIMEOUT: Duration = ROUND_TIMEOUT;
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+// Events kept for subscribers which fall behind, older ones are dropped
+const EVENT_CAPACITY: usize = 128;
+
 pub struct Libp2pHost<S> {
     pub identity: Keypair,
     pub local_peer_id: PeerId,
     pub swarm: Swarm<Behaviour>,
-    pub signer: S,
-    responses: HashMap<RequestId, UnboundedSender<SignResponse>>,
+    pub signer: Arc<S>,
+    // Requests of the peers are signed in their own task, which sends the outcome here
+    sign_outcomes: UnboundedSender<SignOutcome>,
+    signed: UnboundedReceiver<SignOutcome>,
+    responses: HashMap<RequestId, PendingRequest>,
+    // Relays to reserve a slot on while the node isn't publicly reachable
+    relays: Relays,
+    // Remote addresses of the open connections of every peer
+    connections: HashMap<PeerId, Vec<Multiaddr>>,
+    // What peers told about themselves over identify
+    identified: HashMap<PeerId, IdentifiedPeer>,
+    // Move the peers over to QUIC when they listen on it
+    quic: bool,
+    metrics: HostMetrics,
+    swarm_metrics: Option<SwarmMetrics>,
+    events: broadcast::Sender<HostEvent>,
+}
+
+// A sign request sent to a peer, waiting for its response
+struct PendingRequest {
+    tx: UnboundedSender<SignResponse>,
+    peer: PeerId,
+    sent_at: Instant,
+    // Sign round the request belongs to
+    span: Span,
 }
 
 impl<S> Libp2pHost<S>
 where
     S: Signer,
 {
-    pub async fn new(
-        keypair: Option<Keypair>,
-        psk: Option<String>,
-        signer: S,
-    ) -> Result<Self, Box<dyn Error>> {
-        let kp = match keypair {
-            Some(kp) => kp,
-            None => Keypair::generate_ed25519(),
-        };
+    /// Configure a host signing with `signer`.
+    pub fn builder(signer: S) -> HostBuilder<S> {
+        HostBuilder::new(signer)
+    }
 
-        let local_peer_id = PeerId::from(kp.public());
-        let (behaviour, transport) =
-            Behaviour::new_behaviour_and_transport(&kp, local_peer_id, psk)?;
+    /// Register the metrics of the swarm and of the signing protocol in `registry`.
+    pub fn with_metrics(mut self, registry: &mut Registry) -> Self {
+        self.swarm_metrics = Some(SwarmMetrics::new(registry));
+        self.metrics.register(registry);
+        self
+    }
 
-        let mut swarm =
-            SwarmBuilder::with_tokio_executor(transport, behaviour, local_peer_id).build();
+    /// Add a relay to reserve a slot on, so peers behind NAT can reach this node.
+    /// With several relays the node moves on to the next one when a relay fails.
+    pub async fn connect_to_relay(&mut self, relay: String) -> Result<(), Box<dyn Error>> {
+        info!("adding relay: {}", relay);
+        let relay = self.relays.add(relay.parse()?)?.clone();
 
-        swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
+        // The relays also tell whether we are reachable without them
+        let behaviour = self.swarm.behaviour_mut();
+        behaviour.allowlist.always_allow(relay.peer_id);
+        behaviour
+            .autonat
+            .add_server(relay.peer_id, Some(relay.addr));
 
-        Ok(Libp2pHost {
-            identity: kp,
-            local_peer_id,
-            swarm,
-            signer,
-            responses: HashMap::default(),
-        })
+        // Reachability is unknown until AutoNAT probed it, reserve a slot in the meantime
+        self.reserve_relay();
+        Ok(())
     }
 
-    pub async fn connect_to_relay(&mut self, relay: String) -> Result<(), Box<dyn Error>> {
-        info!("connecting to relay: {}", relay);
-        let relay_addr: Multiaddr = relay.parse()?;
+    // Listen on the circuit address of the current relay so other peers can find us
+    fn reserve_relay(&mut self) {
+        if self.relays.listener.is_some() || self.relays.retry_at.is_some() {
+            return;
+        }
+        if let Some(relay) = self.relays.current().cloned() {
+            info!(peer_id = %relay.peer_id, "reserving a slot on relay");
+            match self.swarm.listen_on(relay.circuit_addr()) {
+                Ok(listener) => self.relays.listener = Some(listener),
+                Err(e) => self.next_relay(relay.peer_id, &e.to_string()),
+            }
+        }
+    }
 
-        let dest_relay_addr = relay_addr.clone().with(Protocol::P2pCircuit);
+    // The reservation on the current relay failed or was lost
+    fn relay_failed(&mut self, reason: &str) {
+        // Only while reserving, and once per failure however many events report it
+        if let Some(listener) = self.relays.listener {
+            self.swarm.remove_listener(listener);
+            if let Some(relay) = self.relays.current().map(|relay| relay.peer_id) {
+                self.next_relay(relay, reason);
+            }
+        }
+    }
 
-        // Listen on the destination relay address so other peers can find us
-        self.swarm.listen_on(dest_relay_addr)?;
+    // Give up on `relay` and retry on the next one after a backoff
+    fn next_relay(&mut self, relay: PeerId, reason: &str) {
+        if self.relays.reserved {
+            self.emit(HostEvent::RelayReservationLost { relay });
+        }
+        let exhausted = self.relays.failed();
+        warn!(peer_id = %relay, "relay unusable: {}", reason);
+        if exhausted {
+            error!("no relay is usable, peers behind NAT can't reach this node");
+            self.emit(HostEvent::NoRelayAvailable);
+        }
+    }
+
+    fn retry_relay(&mut self) {
+        self.relays.retry_at = None;
+        if !self.swarm.behaviour().autonat.nat_status().is_public() {
+            self.reserve_relay();
+        }
+    }
+
+    // A publicly reachable node leaves the relay slot to the nodes that need one
+    fn release_relay(&mut self) {
+        self.relays.retry_at = None;
+        if let Some(listener) = self.relays.listener.take() {
+            info!("reachable without the relay, releasing the reservation");
+            self.swarm.remove_listener(listener);
+            self.relays.reserved = false;
+        }
+    }
+
+    fn on_nat_status(&mut self, old: NatStatus, new: NatStatus) {
+        info!("reachability changed from {:?} to {:?}", old, new);
+        if let NatStatus::Public(address) = &old {
+            self.swarm.remove_external_address(address);
+        }
+
+        match &new {
+            NatStatus::Public(address) => {
+                // Confirmed by a peer dialing back, advertise it through identify
+                self.swarm
+                    .add_external_address(address.clone(), AddressScore::Infinite);
+                self.release_relay();
+            }
+            NatStatus::Private | NatStatus::Unknown => self.reserve_relay(),
+        }
+        self.emit(HostEvent::ReachabilityChanged { status: new });
+    }
+
+    // Prefer QUIC for the peers which listen on it: dial their QUIC addresses when none
+    // of the connections is over QUIC yet. The other connections close once idle.
+    fn dial_quic(&mut self, peer_id: PeerId, listen_addrs: &[Multiaddr]) {
+        if !self.quic
+            || self
+                .connections
+                .get(&peer_id)
+                .is_some_and(|addrs| addrs.iter().any(is_quic))
+        {
+            return;
+        }
+        let addrs = listen_addrs
+            .iter()
+            .filter(|addr| is_quic(addr))
+            .cloned()
+            .collect::<Vec<_>>();
+        if addrs.is_empty() {
+            return;
+        }
+
+        debug!(%peer_id, "dialing over QUIC: {:?}", addrs);
+        let opts = DialOpts::peer_id(peer_id)
+            .condition(PeerCondition::NotDialing)
+            .addresses(addrs)
+            .build();
+        if let Err(e) = self.swarm.dial(opts) {
+            debug!(%peer_id, "failed to dial over QUIC: {}", e);
+        }
+    }
+
+    // Connect to a node found on the local network, unless already connected
+    fn dial_discovered(&mut self, peer_id: PeerId, addrs: Vec<Multiaddr>) {
+        if self.swarm.is_connected(&peer_id)
+            || !self.swarm.behaviour().allowlist.is_allowed(&peer_id)
+        {
+            return;
+        }
+        info!(%peer_id, "discovered on the local network: {:?}", addrs);
+        let opts = DialOpts::peer_id(peer_id)
+            .condition(PeerCondition::Disconnected)
+            .addresses(addrs)
+            .build();
+        if let Err(e) = self.swarm.dial(opts) {
+            debug!(%peer_id, "failed to dial discovered peer: {}", e);
+        }
+    }
 
+    // Only keep the connections of the allowed peers from now on
+    fn allow_peers(&mut self, peers: HashSet<PeerId>) {
+        info!("allowing {} peers", peers.len());
+        let allowlist = &mut self.swarm.behaviour_mut().allowlist;
+        allowlist.set(peers);
+
+        let denied = self
+            .swarm
+            .connected_peers()
+            .filter(|peer| !self.swarm.behaviour().allowlist.is_allowed(peer))
+            .cloned()
+            .collect::<Vec<_>>();
+        for peer in denied {
+            warn!(%peer, "disconnecting peer removed from the allowlist");
+            let _ = self.swarm.disconnect_peer_id(peer);
+        }
+    }
+
+    /// Listen on an additional address, next to the ones the host was built with.
+    pub fn listen_on(&mut self, addr: Multiaddr) -> Result<(), Box<dyn Error>> {
+        self.swarm.listen_on(addr)?;
         Ok(())
     }
 
@@ -82,71 +282,342 @@ where
     }
 
     pub fn run(mut self) -> Handler {
-        let (tx, mut rx) = mpsc::unbounded_channel::<SignRequestForPeers>();
+        let (tx, mut rx) = mpsc::unbounded_channel::<Command>();
+        let local_peer_id = self.local_peer_id;
+        let metrics = self.metrics.clone();
+        let events = self.events.clone();
 
-        tokio::spawn(async move {
+        let task = tokio::spawn(async move {
             loop {
+                let relay_retry = self.relays.retry_at;
                 tokio::select! {
-                    swarm_event = self.swarm.select_next_some() => self.handle_swarm_event(swarm_event).await.unwrap(),
-
-                    request = rx.recv() => {
-                        debug!("received request to send to peers: {:?}", request);
-                        let peers = self.swarm.connected_peers().cloned().collect::<Vec<_>>();
-                        if let Some(r) = request {
-                            for peer in peers {
-                                //TODO: may be generate the request id first so we can update the
-                                // responses map BEFORE we send the message so we have zero chances that
-                                // we receive a response before we updat the map
-                                let request_id = self
-                                    .swarm
-                                    .behaviour_mut()
-                                    .request_response
-                                    .send_request(&peer, r.request.clone());
-                                debug!("sent request with id: {:?} to peer {:?}", request_id, peer);
-                                self.responses.insert(request_id, r.tx.clone());
-                            }
+                    swarm_event = self.swarm.select_next_some() => self.on_swarm_event(swarm_event).await,
+
+                    Some(outcome) = self.signed.recv() => self.on_sign_outcome(outcome),
+
+                    _ = time::sleep_until(relay_retry.unwrap_or_else(time::Instant::now)), if relay_retry.is_some() => {
+                        self.retry_relay()
+                    }
+
+                    command = rx.recv() => match command {
+                        Some(Command::Sign(r)) => self.send_to_peers(r),
+                        Some(Command::Status(tx)) => {
+                            let _ = tx.send(self.status());
+                        }
+                        Some(Command::Peers(tx)) => {
+                            let _ = tx.send(self.peers());
+                        }
+                        Some(Command::ListenAddrs(tx)) => {
+                            let _ = tx.send(self.swarm.listeners().cloned().collect());
+                        }
+                        Some(Command::ExternalAddrs(tx)) => {
+                            let addrs = self.swarm.external_addresses().map(|record| record.addr.clone());
+                            let _ = tx.send(addrs.collect());
+                        }
+                        Some(Command::RelayStatus(tx)) => {
+                            let _ = tx.send(self.relay_status());
                         }
+                        Some(Command::KnownPeers(tx)) => {
+                            let _ = tx.send(self.identified.values().cloned().collect());
+                        }
+                        Some(Command::AllowPeers(peers)) => self.allow_peers(peers),
+                        // Asked to stop, or every handler is gone
+                        Some(Command::Shutdown) | None => break,
                     },
                 }
             }
+
+            self.shutdown().await;
         });
 
-        Handler::new(tx)
+        Handler::new(tx, local_peer_id, metrics, events, task)
+    }
+
+    fn send_to_peers(&mut self, r: SignRequestForPeers) {
+        let peers = self.swarm.connected_peers().cloned().collect::<Vec<_>>();
+        for peer in peers {
+            //TODO: may be generate the request id first so we can update the
+            // responses map BEFORE we send the message so we have zero chances that
+            // we receive a response before we updat the map
+            let request_id = self
+                .swarm
+                .behaviour_mut()
+                .request_response
+                .send_request(&peer, r.request.clone());
+            debug!(parent: &r.span, %peer, %request_id, "sent sign request");
+            self.responses.insert(
+                request_id,
+                PendingRequest {
+                    tx: r.tx.clone(),
+                    peer,
+                    sent_at: Instant::now(),
+                    span: r.span.clone(),
+                },
+            );
+        }
+    }
+
+    // Let the requests in flight finish, then close the connections to the peers
+    async fn shutdown(&mut self) {
+        info!(
+            "shutting down, waiting for {} pending requests",
+            self.responses.len()
+        );
+        let drain = async {
+            while !self.responses.is_empty() {
+                tokio::select! {
+                    event = self.swarm.select_next_some() => self.on_swarm_event(event).await,
+                    Some(outcome) = self.signed.recv() => self.on_sign_outcome(outcome),
+                }
+            }
+        };
+        if time::timeout(DRAIN_TIMEOUT, drain).await.is_err() {
+            warn!("dropping {} pending requests", self.responses.len());
+            self.responses.clear();
+        }
+
+        let peers = self.swarm.connected_peers().cloned().collect::<Vec<_>>();
+        for peer in peers {
+            let _ = self.swarm.disconnect_peer_id(peer);
+        }
+        let close = async {
+            while self.swarm.connected_peers().next().is_some() {
+                let event = self.swarm.select_next_some().await;
+                self.on_swarm_event(event).await;
+            }
+        };
+        if time::timeout(CLOSE_TIMEOUT, close).await.is_err() {
+            warn!("connections did not close in time");
+        }
+        info!("swarm stopped");
+    }
+
+    fn status(&self) -> NetworkStatus {
+        NetworkStatus {
+            connected_peers: self.swarm.connected_peers().cloned().collect(),
+            relay: self.relays.current().map(|relay| relay.peer_id),
+            relay_reserved: self.relays.reserved,
+            nat_status: self.swarm.behaviour().autonat.nat_status(),
+        }
+    }
+
+    fn peers(&self) -> Vec<PeerInfo> {
+        self.connections
+            .iter()
+            .map(|(peer_id, addresses)| PeerInfo {
+                peer_id: *peer_id,
+                addresses: addresses.clone(),
+            })
+            .collect()
+    }
+
+    fn relay_status(&self) -> RelayStatus {
+        RelayStatus {
+            relay: self.relays.current().map(|relay| relay.peer_id),
+            reserved: self.relays.reserved,
+        }
+    }
+
+    async fn on_swarm_event(&mut self, event: SwarmEvent<Event, THandlerErr<Behaviour>>) {
+        if let Err(e) = self.handle_swarm_event(event).await {
+            self.report(e);
+        }
+    }
+
+    fn on_sign_outcome(&mut self, outcome: SignOutcome) {
+        if let Err(e) = send_sign_response(self, outcome) {
+            self.report(e);
+        }
+    }
+
+    // Failures are reported to the application, they must never stop the swarm
+    fn report(&self, e: SignRequestResponseError) {
+        match e {
+            // Refusals are logged in the span of the request already
+            SignRequestResponseError::FailedToSign { .. }
+            | SignRequestResponseError::RoundEnded { .. } => debug!("{}", e),
+            _ => warn!("{}", e),
+        }
+        self.emit(HostEvent::Error(e));
+    }
+
+    pub(crate) fn emit(&self, event: HostEvent) {
+        // Fails only when nobody subscribed, which is fine
+        let _ = self.events.send(event);
     }
 
     async fn handle_swarm_event(
         &mut self,
         event: SwarmEvent<Event, THandlerErr<Behaviour>>,
     ) -> Result<(), SignRequestResponseError> {
+        if let Some(metrics) = &self.swarm_metrics {
+            metrics.record(&event);
+            match &event {
+                SwarmEvent::Behaviour(Event::Identify(e)) => metrics.record(e),
+                SwarmEvent::Behaviour(Event::Ping(e)) => metrics.record(e),
+                SwarmEvent::Behaviour(Event::Dcutr(e)) => metrics.record(e),
+                _ => {}
+            }
+        }
+
         match event {
             SwarmEvent::Behaviour(Event::RequestResponse(event)) => {
                 handle_request_response(self, event).await?
             }
             SwarmEvent::Behaviour(Event::Identify(event)) => {
-                info!("found identify event: {:?}", event);
+                debug!("identify event: {:?}", event);
+                if let IdentifyEvent::Received { peer_id, info } = event {
+                    self.dial_quic(peer_id, &info.listen_addrs);
+                    self.identified.insert(
+                        peer_id,
+                        IdentifiedPeer {
+                            peer_id,
+                            protocol_version: info.protocol_version,
+                            agent_version: info.agent_version,
+                            listen_addrs: info.listen_addrs,
+                            protocols: info.protocols,
+                        },
+                    );
+                }
+            }
+            SwarmEvent::Behaviour(Event::Mdns(MdnsEvent::Discovered(discovered))) => {
+                let mut peers: HashMap<PeerId, Vec<Multiaddr>> = HashMap::new();
+                for (peer_id, addr) in discovered {
+                    peers.entry(peer_id).or_default().push(addr);
+                }
+                for (peer_id, addrs) in peers {
+                    self.dial_discovered(peer_id, addrs);
+                }
+            }
+            SwarmEvent::Behaviour(Event::Mdns(MdnsEvent::Expired(expired))) => {
+                for (peer_id, addr) in expired {
+                    debug!(%peer_id, "mdns record expired: {}", addr);
+                }
             }
             SwarmEvent::NewListenAddr { address, .. } => {
                 let peer_id = self.swarm.local_peer_id().to_string();
-                info!("Listening on {:?}, {:?}", address, peer_id);
+                info!(%peer_id, "listening on {}", address);
+                self.emit(HostEvent::NewListenAddr { address });
             }
             SwarmEvent::ConnectionEstablished {
-                peer_id, endpoint, ..
+                peer_id,
+                endpoint,
+                num_established,
+                ..
             } => {
-                info!("Connection established {:?} on {:?}", peer_id, endpoint);
+                info!(%peer_id, "connection established on {:?}", endpoint);
+                self.connections
+                    .entry(peer_id)
+                    .or_default()
+                    .push(endpoint.get_remote_address().clone());
+                self.metrics
+                    .set_connected_peers(self.swarm.connected_peers().count());
+                if num_established.get() == 1 {
+                    self.emit(HostEvent::PeerConnected {
+                        peer: peer_id,
+                        address: endpoint.get_remote_address().clone(),
+                    });
+                }
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                endpoint,
+                num_established,
+                ..
+            } => {
+                debug!(%peer_id, "connection closed");
+                if let Some(addresses) = self.connections.get_mut(&peer_id) {
+                    let address = endpoint.get_remote_address();
+                    if let Some(i) = addresses.iter().position(|a| a == address) {
+                        addresses.remove(i);
+                    }
+                }
+                if num_established == 0 {
+                    self.connections.remove(&peer_id);
+                }
+                self.metrics
+                    .set_connected_peers(self.swarm.connected_peers().count());
+                if num_established == 0 {
+                    self.emit(HostEvent::PeerDisconnected { peer: peer_id });
+                    if self.relays.is_current(&peer_id) {
+                        self.relay_failed("connection closed");
+                    }
+                }
             }
             SwarmEvent::OutgoingConnectionError {
                 peer_id: maybe_peer_id,
                 error,
                 ..
             } => {
-                error!(
-                    "Outgoing connection error: {:?} to peer: {:?}",
-                    error, maybe_peer_id
-                );
+                error!(peer_id = ?maybe_peer_id, "outgoing connection error: {}", error);
+                if let Some(peer_id) = maybe_peer_id {
+                    // A failed dial to a relay we're still connected to, over QUIC for instance
+                    if self.relays.is_current(&peer_id) && !self.swarm.is_connected(&peer_id) {
+                        self.relay_failed(&error.to_string());
+                    }
+                }
             }
-            SwarmEvent::Behaviour(Event::Relay(e)) => info!("{:?}", e),
-            SwarmEvent::Behaviour(Event::Ping(_)) => {
-                info!("pong")
+            SwarmEvent::Behaviour(Event::Relay(e)) => {
+                info!("relay event: {:?}", e);
+                match e {
+                    RelayEvent::ReservationReqAccepted {
+                        relay_peer_id,
+                        renewal,
+                        ..
+                    } => {
+                        self.relays.accepted();
+                        self.emit(HostEvent::RelayReservationAccepted {
+                            relay: relay_peer_id,
+                            renewal,
+                        });
+                    }
+                    RelayEvent::ReservationReqFailed {
+                        relay_peer_id,
+                        error,
+                        ..
+                    } if self.relays.is_current(&relay_peer_id) => {
+                        self.relay_failed(&error.to_string())
+                    }
+                    _ => {}
+                }
+            }
+            SwarmEvent::Behaviour(Event::Dcutr(e)) => match e {
+                DcutrEvent::DirectConnectionUpgradeSucceeded { remote_peer_id } => {
+                    info!(peer_id = %remote_peer_id, "direct connection established");
+                    self.emit(HostEvent::DirectConnectionUpgraded {
+                        peer: remote_peer_id,
+                    });
+                }
+                DcutrEvent::DirectConnectionUpgradeFailed {
+                    remote_peer_id,
+                    error,
+                } => {
+                    // Not fatal, the peers keep talking through the relay
+                    warn!(peer_id = %remote_peer_id, "hole punching failed: {}", error);
+                    self.emit(HostEvent::DirectConnectionUpgradeFailed {
+                        peer: remote_peer_id,
+                        error: error.to_string(),
+                    });
+                }
+                e => debug!("dcutr event: {:?}", e),
+            },
+            SwarmEvent::Behaviour(Event::Autonat(AutonatEvent::StatusChanged { old, new })) => {
+                self.on_nat_status(old, new)
+            }
+            SwarmEvent::Behaviour(Event::Autonat(e)) => debug!("autonat event: {:?}", e),
+            SwarmEvent::ListenerClosed {
+                listener_id,
+                reason,
+                ..
+            } if self.relays.listener == Some(listener_id) => {
+                let reason = match reason {
+                    Ok(()) => "listener closed".to_string(),
+                    Err(e) => e.to_string(),
+                };
+                self.relay_failed(&reason)
+            }
+            SwarmEvent::Behaviour(Event::Ping(e)) => {
+                debug!(peer_id = %e.peer, "ping: {:?}", e.result)
             }
             ev => {
                 debug!("other event: {:?}", ev);
@@ -158,66 +629,259 @@ where
 
 #[derive(Debug)]
 pub struct SignRequestForPeers {
-    request: SignRequest,
+    request: SignedRequest,
     tx: UnboundedSender<SignResponse>,
+    span: Span,
+}
+
+// Requests from the handler to the swarm loop
+#[derive(Debug)]
+enum Command {
+    Sign(SignRequestForPeers),
+    Status(oneshot::Sender<NetworkStatus>),
+    Peers(oneshot::Sender<Vec<PeerInfo>>),
+    ListenAddrs(oneshot::Sender<Vec<Multiaddr>>),
+    ExternalAddrs(oneshot::Sender<Vec<Multiaddr>>),
+    RelayStatus(oneshot::Sender<RelayStatus>),
+    KnownPeers(oneshot::Sender<Vec<IdentifiedPeer>>),
+    AllowPeers(HashSet<PeerId>),
+    Shutdown,
 }
 
+/// Connectivity of the node, as seen by the swarm.
 #[derive(Debug, Clone)]
+pub struct NetworkStatus {
+    pub connected_peers: Vec<PeerId>,
+    /// Relay the node listens through, if any
+    pub relay: Option<PeerId>,
+    /// Whether the relay accepted our reservation, so peers can reach us through it
+    pub relay_reserved: bool,
+    /// Reachability found by AutoNAT, a public node needs no relay
+    pub nat_status: NatStatus,
+}
+
+/// A connected peer and the remote addresses of its connections.
+#[derive(Debug, Clone)]
+pub struct PeerInfo {
+    pub peer_id: PeerId,
+    pub addresses: Vec<Multiaddr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RelayStatus {
+    /// Relay the node listens through, if any
+    pub relay: Option<PeerId>,
+    pub reserved: bool,
+}
+
+/// What a peer told about itself over identify, kept after it disconnects.
+#[derive(Debug, Clone)]
+pub struct IdentifiedPeer {
+    pub peer_id: PeerId,
+    pub protocol_version: String,
+    pub agent_version: String,
+    pub listen_addrs: Vec<Multiaddr>,
+    pub protocols: Vec<String>,
+}
+
+#[derive(Clone)]
 pub struct Handler {
-    tx: UnboundedSender<SignRequestForPeers>,
+    tx: UnboundedSender<Command>,
+    local_peer_id: PeerId,
+    metrics: HostMetrics,
+    events: broadcast::Sender<HostEvent>,
+    // Swarm task, taken by the first shutdown
+    task: Arc<Mutex<Option<JoinHandle<()>>>>,
 }
 
 impl Handler {
-    fn new(tx: UnboundedSender<SignRequestForPeers>) -> Self {
-        Self { tx }
+    fn new(
+        tx: UnboundedSender<Command>,
+        local_peer_id: PeerId,
+        metrics: HostMetrics,
+        events: broadcast::Sender<HostEvent>,
+        task: JoinHandle<()>,
+    ) -> Self {
+        Self {
+            tx,
+            local_peer_id,
+            metrics,
+            events,
+            task: Arc::new(Mutex::new(Some(task))),
+        }
+    }
+
+    /// Peer id of the node, the one its sign requests are signed for.
+    pub fn local_peer_id(&self) -> PeerId {
+        self.local_peer_id
+    }
+
+    /// Receive the events of the swarm from now on, a subscriber which doesn't keep
+    /// up misses the oldest ones.
+    pub fn subscribe(&self) -> broadcast::Receiver<HostEvent> {
+        self.events.subscribe()
+    }
+
+    /// Stop the swarm and wait for it. New sign requests are refused, the ones in
+    /// flight get some time to finish and the connections to the peers are closed.
+    pub async fn shutdown(&self) -> Result<(), Box<dyn Error>> {
+        let task = self.task.lock().expect("task lock poisoned").take();
+        // Fails when the swarm loop is gone already, the task tells why
+        let _ = self.tx.send(Command::Shutdown);
+
+        match task {
+            Some(task) => Ok(task.await?),
+            None => Ok(()),
+        }
+    }
+
+    /// Current connectivity of the node, fails when the swarm loop stopped.
+    pub async fn status(&self) -> Result<NetworkStatus, Box<dyn Error>> {
+        self.query(Command::Status).await
+    }
+
+    /// Connected peers with the addresses they are connected on.
+    pub async fn peers(&self) -> Result<Vec<PeerInfo>, Box<dyn Error>> {
+        self.query(Command::Peers).await
+    }
+
+    /// Addresses the node listens on.
+    pub async fn listen_addrs(&self) -> Result<Vec<Multiaddr>, Box<dyn Error>> {
+        self.query(Command::ListenAddrs).await
+    }
+
+    /// Addresses of the node as seen from outside.
+    pub async fn external_addrs(&self) -> Result<Vec<Multiaddr>, Box<dyn Error>> {
+        self.query(Command::ExternalAddrs).await
+    }
+
+    pub async fn relay_status(&self) -> Result<RelayStatus, Box<dyn Error>> {
+        self.query(Command::RelayStatus).await
+    }
+
+    /// Every peer which identified itself since the node started.
+    pub async fn known_peers(&self) -> Result<Vec<IdentifiedPeer>, Box<dyn Error>> {
+        self.query(Command::KnownPeers).await
+    }
+
+    /// Only accept connections from `peers` and the relays, the peers connected
+    /// which aren't in the list get disconnected. Every peer is accepted until
+    /// a list was set.
+    pub fn allow_peers(&self, peers: HashSet<PeerId>) -> Result<(), Box<dyn Error>> {
+        self.tx
+            .send(Command::AllowPeers(peers))
+            .map_err(|_| "swarm loop stopped")?;
+        Ok(())
+    }
+
+    // Ask the swarm loop for something and wait for its answer
+    async fn query<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> Command,
+    ) -> Result<T, Box<dyn Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(command(tx))
+            .map_err(|_| "swarm loop stopped")?;
+        Ok(rx.await?)
     }
 
+    /// Send `request` to the connected peers, until `min_sigs` of them answered.
+    /// A round which times out or runs out of peers returns the responses it got.
     pub async fn send(
         &self,
         request: SignRequest,
         min_sigs: usize,
+    ) -> Result<Vec<SignResponse>, Box<dyn Error>> {
+        self.send_signed(request.into(), min_sigs).await
+    }
+
+    /// Like [`Handler::send`], with the signature of this node so the peers know
+    /// who started the round.
+    #[instrument(name = "sign_round", skip_all, fields(kind = request.request.kind(), needed = min_sigs))]
+    pub async fn send_signed(
+        &self,
+        request: SignedRequest,
+        min_sigs: usize,
     ) -> Result<Vec<SignResponse>, Box<dyn Error>> {
         let (tx, mut rx) = mpsc::unbounded_channel::<SignResponse>();
 
-        let request = SignRequestForPeers { request, tx };
+        let request = SignRequestForPeers {
+            request,
+            tx,
+            span: Span::current(),
+        };
 
-        self.tx.send(request)?;
+        self.tx
+            .send(Command::Sign(request))
+            .map_err(|_| "swarm loop stopped")?;
+        self.metrics.sign_round(RoundOutcome::Started);
 
         let mut responses: Vec<SignResponse> = Vec::default();
+        let timeout = time::sleep(ROUND_TIMEOUT);
+        tokio::pin!(timeout);
         loop {
             tokio::select! {
-                _ = tokio::time::sleep(std::time::Duration::from_secs(10)) => {
-                    return Err(Box::new(std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out")))
+                _ = &mut timeout => {
+                    self.metrics.sign_round(RoundOutcome::TimedOut);
+                    warn!("timed out with {}/{} responses", responses.len(), min_sigs);
+                    break;
                 }
-                response = rx.recv() => {
-                    match response {
-                        Some(response) => {
-                            debug!("received response: {:?}", response);
-                            responses.push(response);
-                        }
-                        None => {
-                            debug!("no response was received");
+                response = rx.recv() => match response {
+                    Some(response) => {
+                        responses.push(response);
+                        if responses.len() >= min_sigs {
+                            debug!("got {} responses", responses.len());
+                            self.metrics.sign_round(RoundOutcome::Completed);
+                            break;
                         }
-                    };
-
-                    if responses.len() >= min_sigs{
-                        debug!("got enough responses");
+                    }
+                    // Every peer answered or failed, nothing more is coming
+                    None => {
+                        warn!("only got {}/{} responses", responses.len(), min_sigs);
+                        self.metrics.sign_round(RoundOutcome::Incomplete);
                         break;
                     }
-                }
+                },
             }
         }
 
-        return Ok(responses);
+        Ok(responses)
     }
 }
 
+// A direct QUIC address, relayed addresses go over the transport of the relay
+fn is_quic(addr: &Multiaddr) -> bool {
+    addr.iter().any(|p| p == Protocol::QuicV1) && !addr.iter().any(|p| p == Protocol::P2pCircuit)
+}
+
 /// Read the pre shared key file from the given ipfs directory
 pub fn get_psk(path: &Path) -> std::io::Result<Option<String>> {
-    let swarm_key_file = path.join("swarm.key");
+    let swarm_key_file = path.join(SWARM_KEY_FILE);
     match fs::read_to_string(swarm_key_file) {
         Ok(text) => Ok(Some(text)),
         Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
         Err(e) => Err(e),
     }
 }
+
+/// Write a new random pre shared key file in the given directory, an existing one is never overwritten
+pub fn generate_psk(path: &Path) -> std::io::Result<PathBuf> {
+    let swarm_key_file = path.join(SWARM_KEY_FILE);
+    let psk = PreSharedKey::new(rand::random());
+
+    create_private_file(&swarm_key_file)?.write_all(psk.to_string().as_bytes())?;
+    Ok(swarm_key_file)
+}
+
+/// Creates a file only readable by its owner, fails if it already exists.
+pub fn create_private_file(path: &Path) -> std::io::Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
@@ -0,0 +1,92 @@
+use libp2p::PeerId;
+use prometheus_client::{
+    encoding::{EncodeLabelSet, EncodeLabelValue},
+    metrics::{
+        counter::Counter,
+        family::Family,
+        gauge::Gauge,
+        histogram::{exponential_buckets, Histogram},
+    },
+    registry::Registry,
+};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, EncodeLabelValue)]
+pub enum RoundOutcome {
+    Started,
+    Completed,
+    /// Every peer answered or failed before enough responses came in
+    Incomplete,
+    TimedOut,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RoundLabels {
+    outcome: RoundOutcome,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct PeerLabels {
+    peer: String,
+}
+
+/// Metrics of the signing protocol on top of the swarm, the swarm itself is
+/// covered by `libp2p-metrics`.
+#[derive(Clone)]
+pub struct HostMetrics {
+    connected_peers: Gauge,
+    sign_rounds: Family<RoundLabels, Counter>,
+    response_latency: Family<PeerLabels, Histogram, fn() -> Histogram>,
+}
+
+impl Default for HostMetrics {
+    fn default() -> Self {
+        HostMetrics {
+            connected_peers: Gauge::default(),
+            sign_rounds: Family::default(),
+            // From 10ms up to ~20s, beyond the 10s a round waits for responses
+            response_latency: Family::new_with_constructor(|| {
+                Histogram::new(exponential_buckets(0.01, 2.0, 12))
+            }),
+        }
+    }
+}
+
+impl HostMetrics {
+    pub(crate) fn register(&self, registry: &mut Registry) {
+        let registry = registry.sub_registry_with_prefix("p2p");
+        registry.register(
+            "connected_peers",
+            "Number of peers connected to this node",
+            self.connected_peers.clone(),
+        );
+        registry.register(
+            "sign_rounds",
+            "Rounds of sign requests sent to the peers, by outcome",
+            self.sign_rounds.clone(),
+        );
+        registry.register(
+            "response_latency_seconds",
+            "Time it took a peer to answer a sign request",
+            self.response_latency.clone(),
+        );
+    }
+
+    pub(crate) fn set_connected_peers(&self, peers: usize) {
+        self.connected_peers.set(peers as i64);
+    }
+
+    pub(crate) fn sign_round(&self, outcome: RoundOutcome) {
+        self.sign_rounds
+            .get_or_create(&RoundLabels { outcome })
+            .inc();
+    }
+
+    pub(crate) fn response(&self, peer: &PeerId, latency: Duration) {
+        self.response_latency
+            .get_or_create(&PeerLabels {
+                peer: peer.to_string(),
+            })
+            .observe(latency.as_secs_f64());
+    }
+}
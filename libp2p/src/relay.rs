@@ -0,0 +1,206 @@
+use crate::behaviour::{authenticate, new_identify, tcp_transport};
+use futures::prelude::*;
+use libp2p::{
+    autonat,
+    autonat::Event as AutonatEvent,
+    identify,
+    identify::Event as IdentifyEvent,
+    identity::{Keypair, PeerId},
+    ping,
+    ping::Event as PingEvent,
+    relay,
+    relay::Event as RelayEvent,
+    swarm::{AddressScore, Swarm, SwarmBuilder, SwarmEvent},
+    Multiaddr, Transport,
+};
+use libp2p_swarm_derive::NetworkBehaviour;
+use std::{error::Error, time::Duration};
+use tracing::{debug, info, warn};
+
+// A relay of the federation, so signers behind NAT can reach each other. It speaks
+// the same private network as the signers and doesn't take part in signing.
+
+/// Limits of the reservations and circuits a relay accepts.
+#[derive(Debug, Clone)]
+pub struct RelayLimits {
+    pub max_reservations: usize,
+    pub max_reservations_per_peer: usize,
+    pub reservation_duration: Duration,
+    pub max_circuits: usize,
+    pub max_circuits_per_peer: usize,
+    pub max_circuit_duration: Duration,
+    pub max_circuit_bytes: u64,
+}
+
+impl Default for RelayLimits {
+    // Signers keep their circuits open while hole punching fails, far longer
+    // than the defaults of libp2p allow
+    fn default() -> Self {
+        RelayLimits {
+            max_reservations: 128,
+            max_reservations_per_peer: 4,
+            reservation_duration: Duration::from_secs(60 * 60),
+            max_circuits: 64,
+            max_circuits_per_peer: 8,
+            max_circuit_duration: Duration::from_secs(60 * 60),
+            max_circuit_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+impl From<RelayLimits> for relay::Config {
+    fn from(limits: RelayLimits) -> Self {
+        relay::Config {
+            max_reservations: limits.max_reservations,
+            max_reservations_per_peer: limits.max_reservations_per_peer,
+            reservation_duration: limits.reservation_duration,
+            max_circuits: limits.max_circuits,
+            max_circuits_per_peer: limits.max_circuits_per_peer,
+            max_circuit_duration: limits.max_circuit_duration,
+            max_circuit_bytes: limits.max_circuit_bytes,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "RelayServerEvent")]
+pub struct RelayServerBehaviour {
+    pub relay: relay::Behaviour,
+    pub ping: ping::Behaviour,
+    pub identify: identify::Behaviour,
+    // Answers the reachability probes of the signers
+    pub autonat: autonat::Behaviour,
+}
+
+#[derive(Debug)]
+pub enum RelayServerEvent {
+    Relay(RelayEvent),
+    Ping(PingEvent),
+    Identify(IdentifyEvent),
+    Autonat(AutonatEvent),
+}
+
+impl From<RelayEvent> for RelayServerEvent {
+    fn from(event: RelayEvent) -> Self {
+        Self::Relay(event)
+    }
+}
+
+impl From<PingEvent> for RelayServerEvent {
+    fn from(event: PingEvent) -> Self {
+        Self::Ping(event)
+    }
+}
+
+impl From<IdentifyEvent> for RelayServerEvent {
+    fn from(event: IdentifyEvent) -> Self {
+        Self::Identify(event)
+    }
+}
+
+impl From<AutonatEvent> for RelayServerEvent {
+    fn from(event: AutonatEvent) -> Self {
+        Self::Autonat(event)
+    }
+}
+
+pub struct RelayServer {
+    pub local_peer_id: PeerId,
+    pub swarm: Swarm<RelayServerBehaviour>,
+    // Reservations carry the addresses of the relay, the listen addresses unless told otherwise
+    announce_listen_addrs: bool,
+}
+
+impl RelayServer {
+    pub fn new(
+        keypair: Keypair,
+        psk: Option<String>,
+        limits: RelayLimits,
+    ) -> Result<Self, Box<dyn Error>> {
+        let local_peer_id = PeerId::from(keypair.public());
+        let transport = authenticate(&keypair, tcp_transport().boxed(), psk)?;
+
+        let behaviour = RelayServerBehaviour {
+            relay: relay::Behaviour::new(local_peer_id, limits.into()),
+            ping: ping::Behaviour::new(ping::Config::new()),
+            identify: new_identify(&keypair),
+            autonat: autonat::Behaviour::new(local_peer_id, autonat::Config::default()),
+        };
+        let swarm = SwarmBuilder::with_tokio_executor(transport, behaviour, local_peer_id).build();
+
+        Ok(RelayServer {
+            local_peer_id,
+            swarm,
+            announce_listen_addrs: true,
+        })
+    }
+
+    pub fn listen_on(&mut self, addr: Multiaddr) -> Result<(), Box<dyn Error>> {
+        self.swarm.listen_on(addr)?;
+        Ok(())
+    }
+
+    /// Announce `addr` to the peers holding a reservation, instead of the listen
+    /// addresses. Needed when the relay is reached through a NAT or a load balancer.
+    pub fn add_external_address(&mut self, addr: Multiaddr) {
+        self.announce_listen_addrs = false;
+        self.swarm
+            .add_external_address(addr, AddressScore::Infinite);
+    }
+
+    /// Relay for the peers until `shutdown` resolves.
+    pub async fn run(mut self, shutdown: impl Future<Output = ()>) {
+        futures::pin_mut!(shutdown);
+        loop {
+            tokio::select! {
+                event = self.swarm.select_next_some() => self.handle_swarm_event(event),
+                _ = &mut shutdown => break,
+            }
+        }
+        info!("relay stopped");
+    }
+
+    fn handle_swarm_event<E: std::fmt::Debug>(&mut self, event: SwarmEvent<RelayServerEvent, E>) {
+        match event {
+            SwarmEvent::NewListenAddr { address, .. } => {
+                info!(peer_id = %self.local_peer_id, "listening on {}", address);
+                if self.announce_listen_addrs {
+                    self.swarm
+                        .add_external_address(address, AddressScore::Infinite);
+                }
+            }
+            SwarmEvent::Behaviour(RelayServerEvent::Relay(e)) => match e {
+                RelayEvent::ReservationReqAccepted { src_peer_id, .. } => {
+                    info!(peer_id = %src_peer_id, "reservation accepted");
+                }
+                RelayEvent::ReservationReqDenied { src_peer_id } => {
+                    warn!(peer_id = %src_peer_id, "reservation denied");
+                }
+                RelayEvent::ReservationTimedOut { src_peer_id } => {
+                    info!(peer_id = %src_peer_id, "reservation timed out");
+                }
+                RelayEvent::CircuitReqAccepted {
+                    src_peer_id,
+                    dst_peer_id,
+                } => {
+                    info!(src = %src_peer_id, dst = %dst_peer_id, "circuit opened");
+                }
+                RelayEvent::CircuitReqDenied {
+                    src_peer_id,
+                    dst_peer_id,
+                } => {
+                    warn!(src = %src_peer_id, dst = %dst_peer_id, "circuit denied");
+                }
+                e => debug!("relay event: {:?}", e),
+            },
+            SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                debug!(%peer_id, "connection established");
+            }
+            SwarmEvent::ConnectionClosed { peer_id, .. } => {
+                debug!(%peer_id, "connection closed");
+            }
+            ev => debug!("other event: {:?}", ev),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use libp2p::{core::transport::ListenerId, multiaddr::Protocol, Multiaddr, PeerId};
+use std::time::Duration;
+use tokio::time::Instant;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone)]
+pub(crate) struct Relay {
+    pub peer_id: PeerId,
+    pub addr: Multiaddr,
+}
+
+impl Relay {
+    pub fn circuit_addr(&self) -> Multiaddr {
+        self.addr.clone().with(Protocol::P2pCircuit)
+    }
+}
+
+// The relays a node can reserve a slot on. One of them is used at a time, a failure
+// moves on to the next one after a backoff which grows until a reservation succeeds.
+#[derive(Default)]
+pub(crate) struct Relays {
+    candidates: Vec<Relay>,
+    current: usize,
+    /// Listener on the circuit address of the current relay, while a reservation is wanted
+    pub listener: Option<ListenerId>,
+    pub reserved: bool,
+    backoff: Duration,
+    /// When to try the current relay again after a failure
+    pub retry_at: Option<Instant>,
+    // Reservations failed in a row since the last accepted one
+    failures: usize,
+}
+
+impl Relays {
+    /// Add a relay, its address must end with its peer id.
+    pub fn add(&mut self, addr: Multiaddr) -> Result<&Relay, String> {
+        let peer_id = match addr.iter().last() {
+            Some(Protocol::P2p(hash)) => PeerId::from_multihash(hash)
+                .map_err(|_| format!("invalid peer id in relay address {}", addr))?,
+            _ => {
+                return Err(format!(
+                    "relay address {} doesn't end with /p2p/<peer id>",
+                    addr
+                ))
+            }
+        };
+        self.candidates.push(Relay { peer_id, addr });
+        Ok(&self.candidates[self.candidates.len() - 1])
+    }
+
+    pub fn current(&self) -> Option<&Relay> {
+        self.candidates.get(self.current)
+    }
+
+    pub fn is_current(&self, peer_id: &PeerId) -> bool {
+        self.current().map(|relay| relay.peer_id) == Some(*peer_id)
+    }
+
+    pub fn accepted(&mut self) {
+        self.reserved = true;
+        self.failures = 0;
+        self.backoff = MIN_BACKOFF;
+    }
+
+    /// Give up on the current relay and schedule a try on the next one. Returns
+    /// true when every relay failed since the last accepted reservation.
+    pub fn failed(&mut self) -> bool {
+        self.listener = None;
+        self.reserved = false;
+        self.failures += 1;
+        self.current = (self.current + 1) % self.candidates.len().max(1);
+
+        self.backoff = self.backoff.max(MIN_BACKOFF);
+        self.retry_at = Some(Instant::now() + self.backoff);
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+
+        self.failures.is_multiple_of(self.candidates.len().max(1))
+    }
+}
@@ -1,11 +1,33 @@
-use crate::types::{SignRequest, SignResponse};
+use crate::types::{RequestSignature, SignRequest, SignResponse};
+use async_trait::async_trait;
+use libp2p::PeerId;
 
 #[derive(Debug, thiserror::Error, Clone, Copy)]
 pub enum SignerError {
     #[error("invalid request")]
     InvalidRequest,
+    #[error("failed to sign")]
+    FailedToSign,
+    #[error("request was already processed")]
+    AlreadyProcessed,
+    #[error("request origin is not authorized")]
+    Unauthorized,
 }
 
+/// Where a sign request comes from.
+#[derive(Debug, Clone)]
+pub struct RequestOrigin {
+    /// Peer which sent the request, authenticated by the connection
+    pub peer: PeerId,
+    /// Signature of the request by the Stellar key of the peer, when it signed it
+    pub signature: Option<RequestSignature>,
+}
+
+#[async_trait]
 pub trait Signer: Send + Sync + 'static {
-    fn sign(&self, message: &SignRequest) -> Result<SignResponse, SignerError>;
+    async fn sign(
+        &self,
+        origin: &RequestOrigin,
+        message: &SignRequest,
+    ) -> Result<SignResponse, SignerError>;
 }
@@ -1,6 +1,10 @@
 use bson::{from_slice, to_vec};
+use libp2p::PeerId;
 use serde::{Deserialize, Serialize};
 
+// Keeps the signatures over requests apart from the ones over transactions
+const REQUEST_SIGNATURE_DOMAIN: &[u8] = b"tf-bridge-sign-request";
+
 pub type MintRequest = Vec<u8>;
 pub type StellarRequest = Vec<u8>;
 
@@ -12,6 +16,41 @@ pub enum SignRequest {
 
 pub type SignResponse = Vec<u8>;
 
+/// Signature of the node which started a sign round, by its Stellar key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestSignature {
+    /// Stellar address of the key
+    pub public_key: String,
+    /// Signature over the [`SignRequest::signing_payload`] of the request
+    pub signature: Vec<u8>,
+}
+
+/// A sign request as sent to the peers, with the signature of its originator.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedRequest {
+    pub request: SignRequest,
+    pub signature: Option<RequestSignature>,
+}
+
+impl SignRequest {
+    /// Name of the request for logs and metrics, the payload itself is binary.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SignRequest::MintRequest(_) => "mint",
+            SignRequest::StellarRequest(_) => "stellar",
+        }
+    }
+
+    /// What the originator signs: the request and its own peer id, so another
+    /// peer can't send it on as its own.
+    pub fn signing_payload(&self, origin: &PeerId) -> Result<Vec<u8>, bson::ser::Error> {
+        let mut payload = REQUEST_SIGNATURE_DOMAIN.to_vec();
+        payload.extend(origin.to_bytes());
+        payload.extend(to_vec(self)?);
+        Ok(payload)
+    }
+}
+
 impl TryFrom<&[u8]> for SignRequest {
     type Error = bson::de::Error;
 
@@ -24,6 +63,32 @@ impl TryInto<Vec<u8>> for SignRequest {
     type Error = bson::ser::Error;
 
     fn try_into(self) -> Result<Vec<u8>, Self::Error> {
-        Ok(to_vec(&self)?)
+        to_vec(&self)
+    }
+}
+
+impl From<SignRequest> for SignedRequest {
+    fn from(request: SignRequest) -> Self {
+        SignedRequest {
+            request,
+            signature: None,
+        }
+    }
+}
+
+// Nodes which don't sign their requests yet send the bare request
+impl TryFrom<&[u8]> for SignedRequest {
+    type Error = bson::de::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        from_slice(value).or_else(|_| SignRequest::try_from(value).map(SignedRequest::from))
+    }
+}
+
+impl TryInto<Vec<u8>> for SignedRequest {
+    type Error = bson::ser::Error;
+
+    fn try_into(self) -> Result<Vec<u8>, Self::Error> {
+        to_vec(&self)
     }
 }
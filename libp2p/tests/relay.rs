@@ -0,0 +1,143 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use std::time::Duration;
+use tf_libp2p::{
+    event::HostEvent,
+    multiaddr::Protocol,
+    relay::{RelayLimits, RelayServer},
+    traits::{RequestOrigin, Signer, SignerError},
+    types::{SignRequest, SignResponse},
+    Handler, Keypair, Libp2pHost, Multiaddr, PeerId,
+};
+use tokio::{sync::broadcast, time};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// Answers every mint request with its payload
+struct EchoSigner;
+
+#[async_trait]
+impl Signer for EchoSigner {
+    async fn sign(
+        &self,
+        _: &RequestOrigin,
+        message: &SignRequest,
+    ) -> Result<SignResponse, SignerError> {
+        match message {
+            SignRequest::MintRequest(payload) => Ok(payload.clone()),
+            SignRequest::StellarRequest(_) => Err(SignerError::InvalidRequest),
+        }
+    }
+}
+
+// Start a relay on a free local port, returns its address including its peer id
+async fn start_relay() -> Multiaddr {
+    let mut relay =
+        RelayServer::new(Keypair::generate_ed25519(), None, RelayLimits::default()).unwrap();
+    relay
+        .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+        .unwrap();
+
+    let addr = loop {
+        if let libp2p::swarm::SwarmEvent::NewListenAddr { address, .. } =
+            relay.swarm.select_next_some().await
+        {
+            break address;
+        }
+    };
+    relay.add_external_address(addr.clone());
+    let peer_id = relay.local_peer_id;
+    tokio::spawn(relay.run(futures::future::pending()));
+
+    addr.with(Protocol::P2p(peer_id.into()))
+}
+
+// DCUtR closes the relayed connection when a host has no address to offer for the
+// direct upgrade, so every host announces the port it listens on
+fn host() -> Libp2pHost<EchoSigner> {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let addr: Multiaddr = format!("/ip4/127.0.0.1/tcp/{port}").parse().unwrap();
+    Libp2pHost::builder(EchoSigner)
+        .keypair(Keypair::generate_ed25519())
+        .listen_on(addr.clone())
+        .external_address(addr)
+        .build()
+        .unwrap()
+}
+
+async fn wait_for(events: &mut broadcast::Receiver<HostEvent>, what: impl Fn(&HostEvent) -> bool) {
+    time::timeout(TIMEOUT, async {
+        loop {
+            if what(&events.recv().await.unwrap()) {
+                return;
+            }
+        }
+    })
+    .await
+    .expect("event did not happen in time");
+}
+
+async fn reserved_host(relay: &Multiaddr) -> (Handler, PeerId) {
+    let mut host = host();
+    let peer_id = host.local_peer_id;
+    host.connect_to_relay(relay.to_string()).await.unwrap();
+    let handler = host.run();
+
+    let mut events = handler.subscribe();
+    wait_for(&mut events, |e| {
+        matches!(e, HostEvent::RelayReservationAccepted { .. })
+    })
+    .await;
+    (handler, peer_id)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn host_reserves_a_slot_on_a_local_relay() {
+    let relay = start_relay().await;
+    let (handler, _) = reserved_host(&relay).await;
+
+    let status = handler.relay_status().await.unwrap();
+    assert!(status.reserved);
+    handler.shutdown().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn peers_sign_through_a_local_relay() {
+    let relay = start_relay().await;
+    let (signer, signer_id) = reserved_host(&relay).await;
+
+    // Only reachable through the circuit of the relay
+    let circuit = relay
+        .clone()
+        .with(Protocol::P2pCircuit)
+        .with(Protocol::P2p(signer_id.into()));
+    let mut requester = host();
+    requester.swarm.dial(circuit).unwrap();
+    let requester = requester.run();
+
+    time::timeout(TIMEOUT, async {
+        while !requester
+            .peers()
+            .await
+            .unwrap()
+            .iter()
+            .any(|peer| peer.peer_id == signer_id)
+        {
+            time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("no connection through the relay");
+    let responses = requester
+        .send(SignRequest::MintRequest(b"mint".to_vec()), 1)
+        .await
+        .unwrap();
+    assert_eq!(responses, vec![b"mint".to_vec()]);
+
+    requester.shutdown().await.unwrap();
+    signer.shutdown().await.unwrap();
+}
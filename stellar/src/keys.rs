@@ -0,0 +1,47 @@
+use async_trait::async_trait;
+use stellar_base::crypto::KeyPair;
+pub use stellar_base::signature::Signature;
+use stellar_base::PublicKey;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum KeyError {
+    #[error("invalid key: {0}")]
+    InvalidKey(String),
+    #[error("key backend failed to sign: {0}")]
+    Backend(String),
+}
+
+/// An ed25519 key which signs hashes without handing out its secret, so it can
+/// live in memory as well as in a separate signing service.
+#[async_trait]
+pub trait KeyBackend: Send + Sync {
+    fn public_key(&self) -> &PublicKey;
+
+    /// Sign a hash, for instance the hash of a transaction.
+    async fn sign_hash(&self, hash: &[u8]) -> Result<Signature, KeyError>;
+}
+
+/// Key held in memory by this process.
+pub struct LocalKey {
+    kp: KeyPair,
+}
+
+impl LocalKey {
+    pub fn from_secret_seed(seed: &str) -> Result<Self, KeyError> {
+        let kp =
+            KeyPair::from_secret_seed(seed).map_err(|e| KeyError::InvalidKey(e.to_string()))?;
+        Ok(LocalKey { kp })
+    }
+}
+
+#[async_trait]
+impl KeyBackend for LocalKey {
+    fn public_key(&self) -> &PublicKey {
+        self.kp.public_key()
+    }
+
+    async fn sign_hash(&self, hash: &[u8]) -> Result<Signature, KeyError> {
+        Ok(self.kp.sign(hash))
+    }
+}
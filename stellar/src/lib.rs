@@ -1,39 +1,110 @@
 use base64::{engine::general_purpose, Engine as _};
+use keys::{KeyBackend, LocalKey};
 use std::str;
-use stellar_base::crypto::KeyPair;
-pub use stellar_base::{Network, PublicKey, Transaction};
-use stellar_horizon::api::accounts;
+use std::sync::Arc;
+use stellar_base::account::DataValue;
+use stellar_base::crypto::{hash, KeyPair};
+pub use stellar_base::signature::DecoratedSignature;
+use stellar_base::signature::{Signature, SignatureHint};
+use stellar_base::transaction::MIN_BASE_FEE;
+use stellar_base::Operation;
+pub use stellar_base::{Asset, Memo, Network, PublicKey, Transaction};
+use stellar_horizon::api::{accounts, transactions};
 use stellar_horizon::client::{HorizonClient, HorizonHttpClient};
-use stellar_horizon::resources::Signer;
+pub use stellar_horizon::error::Error as HorizonError;
+use stellar_horizon::request::{Order, PageRequest};
+pub use stellar_horizon::resources::Signer;
+pub mod keys;
 pub mod network;
+pub mod payments;
+pub mod transaction;
 use network::StellarNetwork;
 
+// Maximum amount of records Horizon returns in a single page
+pub(crate) const PAGE_LIMIT: u64 = 200;
+// Data entry of a signer account holding the libp2p peer id of its node
+const PEER_ID_DATA_NAME: &str = "id";
+
 pub struct Client {
-    pub kp: KeyPair,
+    key: Arc<dyn KeyBackend>,
     pub network: StellarNetwork,
 }
 
 impl Client {
     pub fn new(seed: &str, network: StellarNetwork) -> Result<Self, Box<dyn std::error::Error>> {
-        let kp = new_keypair(seed)?;
-        Ok(Client { kp, network })
+        let key = LocalKey::from_secret_seed(seed)?;
+        Ok(Client::with_key(Arc::new(key), network))
+    }
+
+    /// Client signing through `key`, which may be held outside of this process.
+    pub fn with_key(key: Arc<dyn KeyBackend>, network: StellarNetwork) -> Self {
+        Client { key, network }
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        self.key.public_key()
+    }
+
+    /// Adds the signature of this key to `tx`.
+    pub async fn sign(&self, tx: &mut Transaction) -> Result<(), Box<dyn std::error::Error>> {
+        let signature = self.sign_transaction(tx).await?;
+        tx.signatures_mut().push(signature);
+        Ok(())
     }
 
-    pub fn sign(&self, tx: Transaction) -> Result<(), Box<dyn std::error::Error>> {
-        Ok(sign(&self.kp, tx, self.network.clone())?)
+    /// Signature of this key over `tx`, to be combined with the ones of the other signers.
+    pub async fn sign_transaction(
+        &self,
+        tx: &Transaction,
+    ) -> Result<DecoratedSignature, Box<dyn std::error::Error>> {
+        let tx_hash = tx.hash(&self.network.clone().to_stellar_network())?;
+        let signature = self.key.sign_hash(&tx_hash).await?;
+
+        Ok(DecoratedSignature::new(
+            SignatureHint::from_public_key(self.public_key()),
+            signature,
+        ))
+    }
+
+    /// Signature of this key over `message`, checked with [`verify_message`].
+    pub async fn sign_message(
+        &self,
+        message: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let signature = self.key.sign_hash(&hash(message)).await?;
+        Ok(signature.to_vec())
+    }
+
+    /// Writes the peer id of this node in the "id" data entry of the account of this key,
+    /// and returns the hash of the transaction.
+    pub async fn register_peer_id(
+        &self,
+        peer_id: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let source = self.public_key().clone();
+        let sequence = transaction::fetch_sequence(&source, self.network.clone()).await?;
+
+        let manage_data = Operation::new_manage_data()
+            .with_data_name(PEER_ID_DATA_NAME.to_string())
+            .with_data_value(Some(DataValue::from_slice(peer_id.as_bytes())?))
+            .build()?;
+        let mut tx = Transaction::builder(source, sequence + 1, MIN_BASE_FEE)
+            .add_operation(manage_data)
+            .into_transaction()?;
+        self.sign(&mut tx).await?;
+
+        transaction::submit_transaction(&tx, self.network.clone()).await
     }
 }
 
-fn new_keypair(seed: &str) -> Result<KeyPair, Box<dyn std::error::Error>> {
-    Ok(KeyPair::from_secret_seed(seed)?)
+/// Whether `signature` is the one of `key` over `message`, as made by [`Client::sign_message`].
+pub fn verify_message(key: &PublicKey, message: &[u8], signature: &[u8]) -> bool {
+    Signature::from_slice(signature).is_ok_and(|s| s.verify(key, &hash(message)))
 }
 
-fn sign(
-    kp: &KeyPair,
-    mut tx: Transaction,
-    network: StellarNetwork,
-) -> Result<(), Box<dyn std::error::Error>> {
-    Ok(tx.sign(kp, &network.to_stellar_network())?)
+/// Generates a new random secret seed
+pub fn generate_secret() -> Result<String, Box<dyn std::error::Error>> {
+    Ok(KeyPair::random()?.secret_key().secret_seed())
 }
 
 pub async fn fetch_singers_from_account(
@@ -43,7 +114,7 @@ pub async fn fetch_singers_from_account(
     let public = PublicKey::from_account_id(&address)?;
     let a = accounts::single(&public);
 
-    let horizon_cl = HorizonHttpClient::new(network.to_network_url())?;
+    let horizon_cl = HorizonHttpClient::new_from_str(&network.to_network_url())?;
 
     let resp = horizon_cl.request(a).await?;
 
@@ -59,21 +130,91 @@ pub async fn fetch_peer_id_from_account(
     let public = PublicKey::from_account_id(&address)?;
     let a = accounts::single(&public);
 
-    let horizon_cl = HorizonHttpClient::new(network.to_network_url())?;
+    let horizon_cl = HorizonHttpClient::new_from_str(&network.to_network_url())?;
 
     let mut resp = horizon_cl.request(a).await?;
 
-    let id = match resp.1.data.remove("id") {
-        Some(d) => Some(
-            String::from_utf8(
-                general_purpose::STANDARD
-                    .decode(d)
-                    .expect("valid base58 id"),
-            )
-            .expect("found invalid utf-8"),
-        ),
+    // Anybody can write the data entries of their account, don't trust them to be valid
+    let id = match resp.1.data.remove(PEER_ID_DATA_NAME) {
+        Some(data) => {
+            let bytes = general_purpose::STANDARD
+                .decode(data)
+                .map_err(|e| format!("peer id of {} is not valid base64: {}", address, e))?;
+            let id = String::from_utf8(bytes)
+                .map_err(|e| format!("peer id of {} is not valid utf-8: {}", address, e))?;
+            Some(id)
+        }
         None => None,
     };
 
     Ok(id)
 }
+
+// Looks for the transactions the target account sent itself with the given hash or return
+// memo. Horizon can't filter on memos so this walks the account's history, newest first,
+// down to the transactions created before `not_before` (unix seconds)
+pub async fn fetch_transactions_with_memo(
+    address: String,
+    memo: &Memo,
+    not_before: i64,
+    network: StellarNetwork,
+) -> Result<Vec<Transaction>, Box<dyn std::error::Error>> {
+    let (memo_type, memo_hash) = match memo {
+        Memo::Hash(hash) => ("hash", hash),
+        Memo::Return(hash) => ("return", hash),
+        _ => return Err("only hash and return memos can be looked up".into()),
+    };
+
+    let public = PublicKey::from_account_id(&address)?;
+    let horizon_cl = HorizonHttpClient::new_from_str(&network.to_network_url())?;
+
+    let mut found = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut req = transactions::for_account(&public)
+            .with_limit(PAGE_LIMIT)
+            .with_order(&Order::Descending);
+        if let Some(cursor) = &cursor {
+            req = req.with_cursor(cursor);
+        }
+
+        let (_, page) = horizon_cl.request(req).await?;
+
+        for tx in &page.records {
+            if tx.created_at.timestamp() < not_before {
+                return Ok(found);
+            }
+            // Anyone can send a transaction with any memo to the account
+            if !tx.successful || tx.source_account != address || tx.memo_type != memo_type {
+                continue;
+            }
+            let memo = match &tx.memo {
+                Some(memo) => general_purpose::STANDARD.decode(memo)?,
+                None => continue,
+            };
+            if memo == memo_hash.as_slice() {
+                // The account doesn't send fee bumps, they can't be a match
+                if let Ok(tx) = transaction::decode_transaction(&tx.envelope_xdr) {
+                    found.push(tx);
+                }
+            }
+        }
+
+        match page.records.last() {
+            Some(last) if page.records.len() as u64 == PAGE_LIMIT => {
+                cursor = Some(last.paging_token.clone())
+            }
+            _ => return Ok(found),
+        }
+    }
+}
+
+/// Unix time, in seconds, the transaction with the given hash made it into a ledger.
+pub async fn fetch_transaction_time(
+    hash: String,
+    network: StellarNetwork,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let horizon_cl = HorizonHttpClient::new_from_str(&network.to_network_url())?;
+    let (_, tx) = horizon_cl.request(transactions::single(hash)).await?;
+    Ok(tx.created_at.timestamp())
+}
@@ -3,17 +3,31 @@ pub use stellar_base::Network;
 const HORIZON_URL: &str = "https://horizon.stellar.org";
 const HORIZON_TEST_URL: &str = "https://horizon-testnet.stellar.org";
 
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum StellarNetwork {
     Testnet,
     Mainnet,
+    /// Any other network, or one of the above through another Horizon instance
+    Custom {
+        horizon_url: String,
+        passphrase: String,
+    },
 }
 
 impl StellarNetwork {
-    pub fn to_network_url(self) -> &'static str {
+    /// Use `horizon_url` instead of the default Horizon instance of the network.
+    pub fn with_horizon_url(self, horizon_url: String) -> Self {
+        StellarNetwork::Custom {
+            horizon_url,
+            passphrase: self.to_stellar_network().passphrase().to_string(),
+        }
+    }
+
+    pub fn to_network_url(self) -> String {
         match self {
-            StellarNetwork::Testnet => HORIZON_TEST_URL,
-            StellarNetwork::Mainnet => HORIZON_URL,
+            StellarNetwork::Testnet => HORIZON_TEST_URL.to_string(),
+            StellarNetwork::Mainnet => HORIZON_URL.to_string(),
+            StellarNetwork::Custom { horizon_url, .. } => horizon_url,
         }
     }
 
@@ -21,6 +35,7 @@ impl StellarNetwork {
         match self {
             StellarNetwork::Mainnet => Network::new_public(),
             StellarNetwork::Testnet => Network::new_test(),
+            StellarNetwork::Custom { passphrase, .. } => Network::new(passphrase),
         }
     }
 }
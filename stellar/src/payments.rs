@@ -0,0 +1,128 @@
+use crate::{network::StellarNetwork, PAGE_LIMIT};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use stellar_base::amount::Amount;
+use stellar_horizon::client::{HorizonClient, HorizonHttpClient};
+use stellar_horizon::page::Page;
+use stellar_horizon::request::Request;
+use url::Url;
+
+/// A payment operation as returned by Horizon, joined with its transaction.
+/// The `stellar-horizon` payment resource doesn't expose the asset, which the
+/// bridge needs to tell TFT deposits apart from other payments.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Payment {
+    pub id: String,
+    pub paging_token: String,
+    pub transaction_successful: bool,
+    pub transaction_hash: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub amount: Option<String>,
+    pub asset_type: Option<String>,
+    pub asset_code: Option<String>,
+    pub asset_issuer: Option<String>,
+    pub transaction: Option<PaymentTransaction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaymentTransaction {
+    pub memo_type: String,
+    /// Base64 encoded for hash and return memos
+    pub memo: Option<String>,
+}
+
+impl Payment {
+    /// Plain payments only, path payments and account creations are not considered
+    pub fn is_payment(&self) -> bool {
+        self.type_ == "payment"
+    }
+
+    /// Amount of the payment in stroops
+    pub fn stroops(&self) -> Option<i64> {
+        let amount = Amount::from_str(self.amount.as_ref()?).ok()?;
+        Some(amount.to_stroops().ok()?.to_i64())
+    }
+}
+
+struct PaymentsRequest {
+    path: String,
+    cursor: Option<String>,
+    order: &'static str,
+    limit: u64,
+}
+
+impl Request for PaymentsRequest {
+    type Response = Page<Payment>;
+
+    fn uri(&self, host: &Url) -> stellar_horizon::error::Result<Url> {
+        let mut url = host.join(&self.path)?;
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("join", "transactions");
+            query.append_pair("order", self.order);
+            query.append_pair("limit", &self.limit.to_string());
+            if let Some(cursor) = &self.cursor {
+                query.append_pair("cursor", cursor);
+            }
+        }
+        Ok(url)
+    }
+}
+
+// Fetches the payments of the target account which come after `cursor`, oldest first
+// At most one page is returned, callers continue from the paging token of the last payment
+pub async fn fetch_payments(
+    address: String,
+    cursor: Option<String>,
+    network: StellarNetwork,
+) -> Result<Vec<Payment>, Box<dyn std::error::Error>> {
+    let req = PaymentsRequest {
+        path: format!("/accounts/{}/payments", address),
+        cursor,
+        order: "asc",
+        limit: PAGE_LIMIT,
+    };
+
+    let horizon_cl = HorizonHttpClient::new_from_str(&network.to_network_url())?;
+    let (_, page) = horizon_cl.request(req).await?;
+
+    Ok(page.records)
+}
+
+pub async fn fetch_payments_for_transaction(
+    tx_hash: String,
+    network: StellarNetwork,
+) -> Result<Vec<Payment>, Box<dyn std::error::Error>> {
+    let req = PaymentsRequest {
+        path: format!("/transactions/{}/payments", tx_hash),
+        cursor: None,
+        order: "asc",
+        limit: PAGE_LIMIT,
+    };
+
+    let horizon_cl = HorizonHttpClient::new_from_str(&network.to_network_url())?;
+    let (_, page) = horizon_cl.request(req).await?;
+
+    Ok(page.records)
+}
+
+// Paging token of the latest payment of the target account, `None` if it never had any
+pub async fn fetch_latest_payment_cursor(
+    address: String,
+    network: StellarNetwork,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let req = PaymentsRequest {
+        path: format!("/accounts/{}/payments", address),
+        cursor: None,
+        order: "desc",
+        limit: 1,
+    };
+
+    let horizon_cl = HorizonHttpClient::new_from_str(&network.to_network_url())?;
+    let (_, page) = horizon_cl.request(req).await?;
+
+    Ok(page.records.into_iter().next().map(|p| p.paging_token))
+}
@@ -0,0 +1,124 @@
+use crate::network::StellarNetwork;
+use std::{error::Error, time::Duration};
+use stellar_base::amount::Stroops;
+use stellar_base::signature::{DecoratedSignature, Signature, SignatureHint};
+use stellar_base::time_bounds::TimeBounds;
+use stellar_base::transaction::{TransactionEnvelope, MIN_BASE_FEE};
+use stellar_base::xdr::{XDRDeserialize, XDRSerialize};
+use stellar_base::{Asset, Memo, Operation, PublicKey, Transaction};
+use stellar_horizon::api::{accounts, transactions};
+use stellar_horizon::client::{HorizonClient, HorizonHttpClient};
+
+// Length of the public key hint in front of an encoded signature
+const HINT_LEN: usize = 4;
+
+/// Builds a single payment from `source`, using the next sequence number of the account.
+/// The payment can't be included in a ledger anymore once `valid_for` passed.
+pub async fn build_payment(
+    source: &str,
+    destination: &str,
+    asset: Asset,
+    amount: i64,
+    memo: Memo,
+    valid_for: Duration,
+    network: StellarNetwork,
+) -> Result<Transaction, Box<dyn Error>> {
+    let source = PublicKey::from_account_id(source)?;
+    let destination = PublicKey::from_account_id(destination)?;
+
+    let sequence = fetch_sequence(&source, network).await?;
+
+    let payment = Operation::new_payment()
+        .with_destination(destination)
+        .with_amount(Stroops::new(amount))?
+        .with_asset(asset)
+        .build()?;
+
+    Ok(Transaction::builder(source, sequence + 1, MIN_BASE_FEE)
+        .with_memo(memo)
+        .with_time_bounds(TimeBounds::valid_for(chrono::Duration::from_std(
+            valid_for,
+        )?))
+        .add_operation(payment)
+        .into_transaction()?)
+}
+
+/// Whether `tx` pays no more than the minimum fee for its operations.
+pub fn has_min_fee(tx: &Transaction) -> bool {
+    tx.fee().to_i64() == MIN_BASE_FEE.to_i64() * tx.operations().len() as i64
+}
+
+/// Unix time after which `tx` can't be included in a ledger, `None` if it never expires.
+pub fn expires_at(tx: &Transaction) -> Option<i64> {
+    tx.time_bounds()
+        .as_ref()
+        .and_then(|bounds| bounds.upper().map(|upper| upper.timestamp()))
+}
+
+/// Current sequence number of an account, transactions of the account must use the next one.
+pub async fn fetch_sequence(
+    account: &PublicKey,
+    network: StellarNetwork,
+) -> Result<i64, Box<dyn Error>> {
+    let horizon_cl = HorizonHttpClient::new_from_str(&network.to_network_url())?;
+    let (_, account) = horizon_cl.request(accounts::single(account)).await?;
+
+    Ok(account.sequence.parse()?)
+}
+
+/// Submits a signed transaction and returns its hash.
+pub async fn submit_transaction(
+    tx: &Transaction,
+    network: StellarNetwork,
+) -> Result<String, Box<dyn Error>> {
+    let horizon_cl = HorizonHttpClient::new_from_str(&network.to_network_url())?;
+    let (_, resp) = horizon_cl
+        .request(transactions::submit(&tx.to_envelope())?)
+        .await?;
+
+    Ok(resp.hash)
+}
+
+pub fn encode_transaction(tx: &Transaction) -> Result<String, Box<dyn Error>> {
+    Ok(tx.to_envelope().xdr_base64()?)
+}
+
+pub fn decode_transaction(xdr: &str) -> Result<Transaction, Box<dyn Error>> {
+    let envelope = TransactionEnvelope::from_xdr_base64(xdr)?;
+    match envelope.as_transaction() {
+        Some(tx) => Ok(tx.clone()),
+        None => Err("fee bump transactions are not supported".into()),
+    }
+}
+
+/// Encodes a signature as its key hint followed by the signature itself.
+pub fn encode_signature(signature: &DecoratedSignature) -> Vec<u8> {
+    let mut encoded = signature.hint().to_vec();
+    encoded.extend(signature.signature().to_vec());
+    encoded
+}
+
+pub fn decode_signature(encoded: &[u8]) -> Result<DecoratedSignature, Box<dyn Error>> {
+    if encoded.len() <= HINT_LEN {
+        return Err("signature too short".into());
+    }
+
+    let hint = SignatureHint::from_slice(&encoded[..HINT_LEN])?;
+    let signature = Signature::from_slice(&encoded[HINT_LEN..])?;
+    Ok(DecoratedSignature::new(hint, signature))
+}
+
+/// Returns the key out of `keys` which produced `signature` over `tx`, if any.
+pub fn verify_signature<'a>(
+    tx: &Transaction,
+    signature: &DecoratedSignature,
+    keys: &'a [PublicKey],
+    network: StellarNetwork,
+) -> Result<Option<&'a PublicKey>, Box<dyn Error>> {
+    let tx_hash = tx.hash(&network.to_stellar_network())?;
+
+    Ok(keys.iter().find(|key| {
+        SignatureHint::from_public_key(key) == *signature.hint()
+            && signature.signature().verify(key, &tx_hash)
+    }))
+}